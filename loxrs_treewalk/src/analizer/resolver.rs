@@ -1,4 +1,5 @@
 use crate::ast::{expr::*, stmt::*, ExprVisitor, StmtVisitor};
+use crate::lexer::token::Location;
 use std::collections::HashMap;
 
 // TODO: consider using macros to implement Resolver
@@ -8,19 +9,43 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum SemantcicError {
-    // TODO: reporst soure position
     #[error("undefined variable: \"{0}\"")]
-    Undefined(String),
+    Undefined(String, Location),
     // TODO: separate recursive declaration error
     #[error("duplicate declaration: \"{0}\"")]
-    DuplicateDeclaration(String),
+    DuplicateDeclaration(String, Location),
     // TODO: better context (consider assining to tuple with pattern match)
     #[error("recursive variable declaration: \"{0}\"")]
-    RecursiveVariableDeclaration(String),
+    RecursiveVariableDeclaration(String, Location),
     #[error("return from non-function")]
-    ReturnFromNonFunction,
+    ReturnFromNonFunction(Location),
     #[error("use of `self` outsie method")]
-    UseOfSelfOutsideMethod,
+    UseOfSelfOutsideMethod(Location),
+    #[error("`break` outside loop")]
+    BreakOutsideLoop(Location),
+    #[error("`continue` outside loop")]
+    ContinueOutsideLoop(Location),
+    #[error("use of `super` outside subclass")]
+    UseOfSuperOutsideSubclass(Location),
+    #[error("class \"{0}\" inherits from itself")]
+    InheritFromSelf(String, Location),
+}
+
+impl SemantcicError {
+    pub fn pos(&self) -> Location {
+        use SemantcicError::*;
+        match self {
+            Undefined(_, pos)
+            | DuplicateDeclaration(_, pos)
+            | RecursiveVariableDeclaration(_, pos)
+            | ReturnFromNonFunction(pos)
+            | UseOfSelfOutsideMethod(pos)
+            | BreakOutsideLoop(pos)
+            | ContinueOutsideLoop(pos)
+            | UseOfSuperOutsideSubclass(pos)
+            | InheritFromSelf(_, pos) => *pos,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -33,54 +58,76 @@ pub enum LoxFnType {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ClassType {
     Class,
+    Subclass,
+    None,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LoopType {
+    Loop,
     None,
 }
 
-type Scope = HashMap<String, bool>;
-// TODO: map id
-type VarUseCache = HashMap<VarUseData, usize>;
+/// A local variable in a scope
+#[derive(Debug, Clone, Copy)]
+struct Local {
+    /// Index in the scope, in order of declaration (same as the runtime `Env`)
+    index: usize,
+    is_initialized: bool,
+}
+
+type Scope = HashMap<String, Local>;
 
-/// Tracks objects in local scope, analizes them and provides a way to map each variable usage
-/// to specific variable in AST.
+/// Tracks objects in local scope, analizes them and maps each variable usage to specific variable
+/// in AST.
 ///
 /// It was first introduced for closures.
-pub struct Resolver<'a> {
-    /// Each scope maps variables to whether it's already initialzied or not.
+pub struct Resolver {
+    /// Each scope maps variables to their slots and whether they're already initialzied or not.
     /// Useful to detect recursive variable definition or duplicates.
     scopes: Vec<Scope>,
     /// State for function resolving.
     current_fn_type: LoxFnType,
     /// Tracks either in the class or not
     current_class_type: ClassType,
-    /// Distances from a scope where each variable is in. Only tracks local variables (see 11.3.2
-    /// for details)
-    // TODO: isize vs usize
-    caches: &'a mut VarUseCache,
+    /// Tracks either in a loop or not (in the current function)
+    current_loop_type: LoopType,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // TODO: consider returning multiple errors
-impl<'a> Resolver<'a> {
-    pub fn new(caches: &'a mut VarUseCache) -> Self {
+impl Resolver {
+    pub fn new() -> Self {
         Self {
             // We don't track global definitions.
             scopes: Vec::new(),
             current_fn_type: LoxFnType::None,
             current_class_type: ClassType::None,
-            caches: caches,
+            current_loop_type: LoopType::None,
         }
     }
 
-    /// Enables to map a local variable to a scope providing the distance to it
+    /// Stores the slot of a local variable (the distance to the scope and the index) in the AST.
+    /// Only tracks local variables (see 11.3.2 for details)
     fn resolve_local_var(&mut self, var: &VarUseData) {
-        if let Some(d) = self
+        let slot = self
             .scopes
             .iter()
             .rev()
             .enumerate()
-            .find(|(d, s)| s.contains_key(&var.name))
-            .map(|(d, s)| d)
-        {
-            self.caches.insert(var.clone(), d);
+            .find_map(|(depth, scope)| {
+                scope.get(&var.name).map(|local| LocalSlot {
+                    depth: depth,
+                    index: local.index,
+                })
+            });
+        if let Some(slot) = slot {
+            var.resolve(slot);
         }
     }
 
@@ -96,27 +143,36 @@ impl<'a> Resolver<'a> {
 
     /// States that the item exists but not initialized yet.
     /// Returns error if it finds duplicates.
-    fn declare(&mut self, name: &str) -> Result<()> {
+    fn declare(&mut self, name: &str, pos: Location) -> Result<()> {
         if self.scopes.len() == 0 {
             return Ok(()); // we don't track global variables (see 11.3.2 of the book for details)
         }
         let scope = self.scopes.last_mut().unwrap();
         if scope.contains_key(name) {
-            return Err(SemantcicError::DuplicateDeclaration(name.to_string()));
+            return Err(SemantcicError::DuplicateDeclaration(name.to_string(), pos));
         }
-        scope.insert(name.to_string(), false);
+        let local = Local {
+            index: scope.len(),
+            is_initialized: false,
+        };
+        scope.insert(name.to_string(), local);
         Ok(())
     }
 
-    /// States that the item is initialized. Panics if it's not declared.
+    /// States that the item is initialized, declaring it if it's not declared yet.
     fn define(&mut self, name: &str) {
         if self.scopes.len() == 0 {
             return; // we don't track global variables (see 11.3.2 of the book for details)
         }
-        self.scopes
-            .last_mut()
-            .unwrap()
-            .insert(name.to_string(), true);
+        let scope = self.scopes.last_mut().unwrap();
+        let index = scope.len();
+        scope
+            .entry(name.to_string())
+            .or_insert(Local {
+                index: index,
+                is_initialized: false,
+            })
+            .is_initialized = true;
     }
 
     /// Implemented with Visitor pattern
@@ -154,40 +210,57 @@ impl<'a> Resolver<'a> {
     }
 
     /// Starts tracking state
-    fn resolve_fn_before(&mut self, fn_type: LoxFnType) -> LoxFnType {
+    fn resolve_fn_before(&mut self, fn_type: LoxFnType) -> (LoxFnType, LoopType) {
         // tracking state
-        let enclosing = self.current_fn_type;
+        let enclosing = (self.current_fn_type, self.current_loop_type);
         self.current_fn_type = fn_type;
+        // we can't `break` out of a function
+        self.current_loop_type = LoopType::None;
         self.begin_scope();
         enclosing
     }
 
     /// Ends tracking state
-    fn resolve_fn_after(&mut self, enclosing: LoxFnType) {
+    fn resolve_fn_after(&mut self, enclosing: (LoxFnType, LoopType)) {
+        self.end_scope();
+        self.current_fn_type = enclosing.0;
+        self.current_loop_type = enclosing.1;
+    }
+
+    /// Resolves the body of a loop in a new scope (with the loop variable if any) tracking state
+    fn resolve_loop_body(&mut self, var: Option<&str>, stmts: &[Stmt]) -> Result<()> {
+        let enclosing = self.current_loop_type;
+        self.current_loop_type = LoopType::Loop;
+        self.begin_scope();
+        if let Some(var) = var {
+            self.define(var);
+        }
+        let result = self.resolve_stmts(stmts);
         self.end_scope();
-        self.current_fn_type = enclosing;
+        self.current_loop_type = enclosing;
+        result
     }
 
     /// Resolves function arguments and the body
     fn impl_resolve_fn(&mut self, f: &FnDeclArgs) -> Result<()> {
         for param in f.params.iter() {
-            self.declare(param)?;
+            self.declare(param, f.pos)?;
             self.define(param);
         }
         self.resolve_stmts(&f.body)
     }
 }
 
-impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
+impl StmtVisitor<Result<()>> for Resolver {
     fn visit_var_decl(&mut self, var: &VarDeclArgs) -> Result<()> {
-        self.declare(&var.name)?;
+        self.declare(&var.name, var.pos)?;
         self.resolve_expr(&var.init)?; // we don't allow to recursively referring to itself
         self.define(&var.name);
         Ok(())
     }
 
     fn visit_fn_decl(&mut self, f: &FnDeclArgs) -> Result<()> {
-        self.declare(&f.name)?;
+        self.declare(&f.name, f.pos)?;
         self.define(&f.name); // we allow to recursively referring to itself
         self.resolve_pure_fn(f, LoxFnType::Fn)
     }
@@ -200,7 +273,7 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
 
     fn visit_if_stmt(&mut self, if_: &IfArgs) -> Result<()> {
         self.resolve_expr(&if_.condition)?;
-        self.resolve_stmts(&if_.if_true.stmts)?;
+        self.resolve_block(&if_.if_true.stmts)?;
         match if_.if_false {
             Some(ElseBranch::ElseIf(ref if_)) => {
                 self.visit_if_stmt(&if_)?;
@@ -219,7 +292,7 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
 
     fn visit_return_stmt(&mut self, ret: &Return) -> Result<()> {
         if self.current_fn_type == LoxFnType::None {
-            Err(SemantcicError::ReturnFromNonFunction)
+            Err(SemantcicError::ReturnFromNonFunction(ret.pos))
         } else {
             self.resolve_expr(&ret.expr)
         }
@@ -227,7 +300,32 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
 
     fn visit_while_stmt(&mut self, while_: &WhileArgs) -> Result<()> {
         self.resolve_expr(&while_.condition)?;
-        self.resolve_block(&while_.block.stmts)
+        self.resolve_loop_body(None, &while_.block.stmts)
+    }
+
+    fn visit_for_stmt(&mut self, for_: &ForArgs) -> Result<()> {
+        match for_.iter {
+            ForIter::Range(ref start, ref end) => {
+                self.resolve_expr(start)?;
+                self.resolve_expr(end)?;
+            }
+            ForIter::Each(ref iter) => self.resolve_expr(iter)?,
+        }
+        self.resolve_loop_body(Some(&for_.var), &for_.block.stmts)
+    }
+
+    fn visit_break_stmt(&mut self, pos: Location) -> Result<()> {
+        if self.current_loop_type == LoopType::None {
+            return Err(SemantcicError::BreakOutsideLoop(pos));
+        }
+        Ok(())
+    }
+
+    fn visit_continue_stmt(&mut self, pos: Location) -> Result<()> {
+        if self.current_loop_type == LoopType::None {
+            return Err(SemantcicError::ContinueOutsideLoop(pos));
+        }
+        Ok(())
     }
 
     fn visit_block_stmt(&mut self, stmts: &Vec<Stmt>) -> Result<()> {
@@ -235,34 +333,71 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
     }
 
     fn visit_class_decl(&mut self, class: &ClassDeclArgs) -> Result<()> {
+        if let Some(ref superclass) = class.superclass {
+            if superclass.name == class.name {
+                return Err(SemantcicError::InheritFromSelf(
+                    class.name.to_string(),
+                    superclass.pos,
+                ));
+            }
+        }
         let enclosing = self.current_class_type;
         self.current_class_type = ClassType::Class;
         // Lox permits to declare a class as a local variable
-        self.declare(&class.name)?;
+        self.declare(&class.name, class.pos)?;
         self.define(&class.name);
+        if let Some(ref superclass) = class.superclass {
+            self.current_class_type = ClassType::Subclass;
+            self.resolve_local_var(superclass);
+            // methods are closed over a scope where `super` is defined
+            self.begin_scope();
+            self.define("super");
+        }
         for method in class.methods.iter() {
-            let enclosing = self.resolve_fn_before(LoxFnType::Method);
-            self.scopes
-                .last_mut()
-                .unwrap()
-                .insert("@".to_string(), true);
-            let result = self.impl_resolve_fn(method);
-            self.resolve_fn_after(enclosing);
+            // methods are bound to an instance in a scope where `@` is defined
+            self.begin_scope();
+            self.define("@");
+            let result = self.resolve_pure_fn(method, LoxFnType::Method);
+            self.end_scope();
             result?;
         }
+        if class.superclass.is_some() {
+            self.end_scope();
+        }
         self.current_class_type = enclosing;
         Ok(())
     }
+
+    fn visit_throw_stmt(&mut self, throw: &ThrowArgs) -> Result<()> {
+        self.resolve_expr(&throw.expr)
+    }
+
+    fn visit_try_stmt(&mut self, try_: &TryArgs) -> Result<()> {
+        self.resolve_block(&try_.body.stmts)?;
+        if let Some(ref catch) = try_.catch {
+            // the error is the first variable in the scope of the `catch` block
+            self.begin_scope();
+            self.define(&catch.var);
+            let result = self.resolve_stmts(&catch.block.stmts);
+            self.end_scope();
+            result?;
+        }
+        if let Some(ref finally) = try_.finally {
+            self.resolve_block(&finally.stmts)?;
+        }
+        Ok(())
+    }
 }
 
-impl<'a> ExprVisitor<Result<()>> for Resolver<'a> {
+impl ExprVisitor<Result<()>> for Resolver {
     fn visit_var_expr(&mut self, var: &VarUseData) -> Result<()> {
         // we forbid recursive variable declaration
         if let Some(scope) = self.scopes.last() {
-            if scope.get(&var.name) == Some(&false) {
+            if scope.get(&var.name).map(|local| local.is_initialized) == Some(false) {
                 // cannot read variable in its own initializer
                 return Err(SemantcicError::RecursiveVariableDeclaration(
                     var.name.to_string(),
+                    var.pos,
                 ));
             }
         }
@@ -313,12 +448,45 @@ impl<'a> ExprVisitor<Result<()>> for Resolver<'a> {
         self.resolve_expr(&set.value)
     }
 
+    fn visit_list_expr(&mut self, list: &ListData) -> Result<()> {
+        for item in list.items.iter() {
+            self.resolve_expr(item)?;
+        }
+        Ok(())
+    }
+
+    fn visit_map_expr(&mut self, map: &MapData) -> Result<()> {
+        for (key, value) in map.entries.iter() {
+            self.resolve_expr(key)?;
+            self.resolve_expr(value)?;
+        }
+        Ok(())
+    }
+
+    fn visit_index_expr(&mut self, index: &IndexData) -> Result<()> {
+        self.resolve_expr(&index.body)?;
+        self.resolve_expr(&index.index)
+    }
+
+    fn visit_set_index_expr(&mut self, set: &SetIndexData) -> Result<()> {
+        self.resolve_expr(&set.body)?;
+        self.resolve_expr(&set.index)?;
+        self.resolve_expr(&set.value)
+    }
+
     fn visit_self_expr(&mut self, self_: &SelfData) -> Result<()> {
-        // TODO: cache to VarUseId and resolve @ here (for performance)
-        // self.caches.insert("@", 0);
-        if self.current_class_type != ClassType::Class {
-            return Err(SemantcicError::UseOfSelfOutsideMethod);
+        if self.current_class_type == ClassType::None {
+            return Err(SemantcicError::UseOfSelfOutsideMethod(self_.pos));
+        }
+        self.resolve_local_var(&self_.var);
+        Ok(())
+    }
+
+    fn visit_super_expr(&mut self, super_: &SuperData) -> Result<()> {
+        if self.current_class_type != ClassType::Subclass {
+            return Err(SemantcicError::UseOfSuperOutsideSubclass(super_.pos));
         }
+        self.resolve_local_var(&super_.var);
         Ok(())
     }
 }
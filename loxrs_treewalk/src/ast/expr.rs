@@ -1,8 +1,7 @@
-use crate::lexer::token::TokenKind;
+use crate::lexer::token::{Location, TokenKind};
+use std::cell::Cell;
 use std::convert::From;
 
-// We need to make `Expr` hashable so that we can map `Expr` to distance
-// in `Resolver`.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Expr {
     Literal(LiteralData),
@@ -18,70 +17,148 @@ pub enum Expr {
     Get(Box<GetUseData>),
     // Assignment to a field of an instance
     Set(Box<SetUseData>),
+    /// `[a, b, c]`
+    List(ListData),
+    /// `{k1: v1, k2: v2}`
+    Map(MapData),
+    /// `xs[i]`
+    Index(Box<IndexData>),
+    /// Assignment to an item of a list or a map
+    SetIndex(Box<SetIndexData>),
     Self_(SelfData),
+    /// `super.method`
+    Super(SuperData),
 }
 
 /// Helpers for constructing / right recursive parsing
 impl Expr {
-    pub fn literal(args: LiteralData) -> Expr {
-        Expr::Literal(args)
+    pub fn literal(value: LiteralValue, pos: Location) -> Expr {
+        Expr::Literal(LiteralData {
+            value: value,
+            pos: pos,
+        })
     }
 
-    pub fn unary(oper: UnaryOper, expr: Expr) -> Expr {
+    pub fn unary(oper: UnaryOper, expr: Expr, pos: Location) -> Expr {
         Expr::Unary(Box::new(UnaryData {
             oper: oper,
             expr: expr,
+            pos: pos,
         }))
     }
 
     /// comparison, addition, or multiplication
-    pub fn binary(left: Expr, oper: BinaryOper, right: Expr) -> Expr {
+    pub fn binary(left: Expr, oper: BinaryOper, right: Expr, pos: Location) -> Expr {
         Expr::Binary(Box::new(BinaryData {
             left: left,
             oper: oper,
             right: right,
+            pos: pos,
         }))
     }
 
-    pub fn logic(left: Expr, oper: LogicOper, right: Expr) -> Expr {
+    pub fn logic(left: Expr, oper: LogicOper, right: Expr, pos: Location) -> Expr {
         Expr::Logic(Box::new(LogicData {
             left: left,
             oper: oper,
             right: right,
+            pos: pos,
         }))
     }
 
-    pub fn group(expr: Expr) -> Expr {
-        Expr::Grouping(Box::new(GroupData { expr: expr }))
+    pub fn group(expr: Expr, pos: Location) -> Expr {
+        Expr::Grouping(Box::new(GroupData {
+            expr: expr,
+            pos: pos,
+        }))
     }
 
-    pub fn var(name: &str, id: VarUseId) -> Expr {
-        Expr::Variable(VarUseData::new(name, id))
+    pub fn var(name: &str, pos: Location) -> Expr {
+        Expr::Variable(VarUseData::new(name, pos))
     }
 
     /// Assignment to a variable
-    pub fn assign(name: &str, expr: Expr, id: VarUseId) -> Expr {
+    pub fn assign(assigned: VarUseData, oper: AssignOper, expr: Expr, pos: Location) -> Expr {
         Expr::Assign(Box::new(AssignData {
-            assigned: VarUseData::new(name, id),
+            assigned: assigned,
+            oper: oper,
             expr: expr,
+            pos: pos,
         }))
     }
 
     /// Assignment to a field of an instance
-    pub fn set(body: Expr, name: &str, value: Expr) -> Expr {
-        Expr::Set(Box::new(SetUseData::new(body, name, value)))
+    pub fn set(body: Expr, name: &str, oper: AssignOper, value: Expr, pos: Location) -> Expr {
+        Expr::Set(Box::new(SetUseData::new(body, name, oper, value, pos)))
+    }
+
+    pub fn get(body: Expr, name: &str, pos: Location) -> Expr {
+        Expr::Get(Box::new(GetUseData::new(body, name, pos)))
+    }
+
+    pub fn list(items: Args, pos: Location) -> Expr {
+        Expr::List(ListData {
+            items: items,
+            pos: pos,
+        })
     }
 
-    pub fn get(body: Expr, name: &str) -> Expr {
-        Expr::Get(Box::new(GetUseData::new(body, name)))
+    pub fn map(entries: Vec<(Expr, Expr)>, pos: Location) -> Expr {
+        Expr::Map(MapData {
+            entries: entries,
+            pos: pos,
+        })
+    }
+
+    pub fn index(body: Expr, index: Expr, pos: Location) -> Expr {
+        Expr::Index(Box::new(IndexData {
+            body: body,
+            index: index,
+            pos: pos,
+        }))
+    }
+
+    /// Assignment to an item of a list or a map
+    pub fn set_index(index: IndexData, oper: AssignOper, value: Expr, pos: Location) -> Expr {
+        Expr::SetIndex(Box::new(SetIndexData {
+            body: index.body,
+            index: index.index,
+            oper: oper,
+            value: value,
+            pos: pos,
+        }))
     }
 
-    pub fn call(callee: Expr, args: Args) -> Self {
+    pub fn call(callee: Expr, args: Args, pos: Location) -> Self {
         Expr::Call(Box::new(CallData {
             callee: callee,
             args: args,
+            pos: pos,
         }))
     }
+
+    /// Source position of the token that represents the expression
+    pub fn pos(&self) -> Location {
+        use Expr::*;
+        match self {
+            Literal(lit) => lit.pos,
+            Unary(unary) => unary.pos,
+            Binary(binary) => binary.pos,
+            Logic(logic) => logic.pos,
+            Grouping(group) => group.pos,
+            Variable(var) => var.pos,
+            Assign(assign) => assign.pos,
+            Call(call) => call.pos,
+            Get(get) => get.pos,
+            Set(set) => set.pos,
+            List(list) => list.pos,
+            Map(map) => map.pos,
+            Index(index) => index.pos,
+            SetIndex(set) => set.pos,
+            Self_(self_) => self_.pos,
+            Super(super_) => super_.pos,
+        }
+    }
 }
 
 impl From<LiteralData> for Expr {
@@ -90,52 +167,60 @@ impl From<LiteralData> for Expr {
     }
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiteralData {
+    pub value: LiteralValue,
+    pub pos: Location,
+}
+
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
-pub enum LiteralData {
+pub enum LiteralValue {
     Nil,
     Bool(bool),
     StringLit(String),
     Number(f64),
 }
 
-impl LiteralData {
-    /// Maps specific tokens to `Option::Some(LiteralData)`
-    pub fn from_token(token: &TokenKind) -> Option<LiteralData> {
+impl LiteralValue {
+    /// Maps specific tokens to `Option::Some(LiteralValue)`
+    pub fn from_token(token: &TokenKind) -> Option<LiteralValue> {
         use TokenKind::*;
         Some(match token {
-            Nil => LiteralData::Nil,
-            True => LiteralData::Bool(true),
-            False => LiteralData::Bool(false),
-            Str(ref s) => LiteralData::StringLit(s.clone()),
-            Num(n) => LiteralData::Number(n.clone()),
+            Nil => LiteralValue::Nil,
+            True => LiteralValue::Bool(true),
+            False => LiteralValue::Bool(false),
+            Str(ref s) => LiteralValue::StringLit(s.clone()),
+            Num(n) => LiteralValue::Number(n.clone()),
             _ => return None,
         })
     }
 }
 
 // They are convenient for writing tests.
-impl From<f64> for LiteralData {
+impl From<f64> for LiteralValue {
     fn from(item: f64) -> Self {
-        LiteralData::Number(item)
+        LiteralValue::Number(item)
     }
 }
 
-impl From<String> for LiteralData {
+impl From<String> for LiteralValue {
     fn from(item: String) -> Self {
-        LiteralData::StringLit(item)
+        LiteralValue::StringLit(item)
     }
 }
 
-impl From<bool> for LiteralData {
+impl From<bool> for LiteralValue {
     fn from(item: bool) -> Self {
-        LiteralData::Bool(item)
+        LiteralValue::Bool(item)
     }
 }
 
+/// Position: the operator
 #[derive(Clone, Debug, PartialEq)]
 pub struct UnaryData {
     pub oper: UnaryOper,
     pub expr: Expr,
+    pub pos: Location,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -155,11 +240,13 @@ impl From<TokenKind> for Option<UnaryOper> {
     }
 }
 
+/// Position: the operator
 #[derive(Clone, Debug, PartialEq)]
 pub struct BinaryData {
     pub left: Expr,
     pub oper: BinaryOper,
     pub right: Expr,
+    pub pos: Location,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -205,12 +292,13 @@ impl From<TokenKind> for Option<BinaryOper> {
     }
 }
 
-/// `&&` or `||`
+/// `&&` or `||`. Position: the operator
 #[derive(Clone, Debug, PartialEq)]
 pub struct LogicData {
     pub left: Expr,
     pub oper: LogicOper,
     pub right: Expr,
+    pub pos: Location,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -230,73 +318,85 @@ impl From<TokenKind> for Option<LogicOper> {
     }
 }
 
-/// `()`
+/// `()`. Position: the left parenthesis
 #[derive(Clone, Debug, PartialEq)]
 pub struct GroupData {
     pub expr: Expr,
-}
-
-/// Enables to track each variable use. It's required by the `Resolver`.
-///
-/// We might be able to use source position instead, but my AST doesn't track that information.
-/// So I embeded ID in AST.
-// TODO: refactor when I add more context to error information
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-pub struct VarUseId {
-    id: usize,
-}
-
-impl VarUseId {
-    pub fn new() -> Self {
-        Self { id: 0 }
-    }
-}
-
-/// Creates new ID.
-pub struct VarUseIdCounter {
-    id: usize,
-}
-
-impl VarUseIdCounter {
-    pub fn new() -> Self {
-        Self { id: 0 }
-    }
-
-    pub fn next(&mut self) -> VarUseId {
-        self.id += 1;
-        VarUseId { id: self.id - 1 }
-    }
+    pub pos: Location,
 }
 
 /// Represents a variable use
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+///
+/// The `Resolver` stores where the variable is in the node itself, so that the result is never
+/// confused with another variable use (e.g. in another REPL input sharing the same positions).
+#[derive(Clone, Debug, PartialEq)]
 pub struct VarUseData {
     pub name: String,
-    /// Unique identity of each variable use
-    pub id: VarUseId,
+    pub pos: Location,
+    /// Set by the `Resolver` if it's a local variable
+    slot: Cell<Option<LocalSlot>>,
 }
 
 impl VarUseData {
-    pub fn new(name: &str, id: VarUseId) -> Self {
+    pub fn new(name: &str, pos: Location) -> Self {
         Self {
             name: name.to_string(),
-            id: id,
+            pos: pos,
+            slot: Cell::new(None),
         }
     }
+
+    /// The slot of the local variable, or `None` if it's a global variable
+    pub fn slot(&self) -> Option<LocalSlot> {
+        self.slot.get()
+    }
+
+    pub fn resolve(&self, slot: LocalSlot) {
+        self.slot.set(Some(slot));
+    }
 }
 
-/// `=`,  only parsed as an expression statement.
+/// Location of a local variable, found by the `Resolver`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalSlot {
+    /// Distance from the current scope to the scope the variable is in
+    pub depth: usize,
+    /// Index of the variable in the scope, in order of declaration
+    pub index: usize,
+}
+
+/// `=` or compound assignment such as `+=`. Position: the operator
 ///
 /// It doesn't contain LHS object 'cause. Instead, it should be gotten from `Env`.
 #[derive(Clone, Debug, PartialEq)]
 pub struct AssignData {
     pub assigned: VarUseData,
+    pub oper: AssignOper,
     pub expr: Expr,
+    pub pos: Location,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum AssignOper {
     Equal,
+    Plus,
+    Minus,
+    Mul,
+    Div,
+}
+
+impl AssignOper {
+    /// The binary operator applied before assignment (if it's a compound assignment)
+    pub fn binary(&self) -> Option<BinaryOper> {
+        use AssignOper::*;
+        Some(match self {
+            Equal => return None,
+            Plus => BinaryOper::Plus,
+            Minus => BinaryOper::Minus,
+            Mul => BinaryOper::Mul,
+            Div => BinaryOper::Div,
+        })
+    }
 }
 
 impl From<TokenKind> for Option<AssignOper> {
@@ -304,6 +404,10 @@ impl From<TokenKind> for Option<AssignOper> {
         use TokenKind::*;
         Some(match item {
             Eq => AssignOper::Equal,
+            PlusEq => AssignOper::Plus,
+            MinusEq => AssignOper::Minus,
+            StarEq => AssignOper::Mul,
+            SlashEq => AssignOper::Div,
             _ => return None,
         })
     }
@@ -311,45 +415,99 @@ impl From<TokenKind> for Option<AssignOper> {
 
 pub type Args = Vec<Expr>;
 
+/// Position: the left parenthesis
 #[derive(Clone, Debug, PartialEq)]
 pub struct CallData {
     pub callee: Expr,
     // FIXME: just use `Args` type
     pub args: Args,
+    pub pos: Location,
 }
 
+/// Position: the field name
 #[derive(Clone, Debug, PartialEq)]
 pub struct GetUseData {
     pub body: Expr,
     pub name: String,
+    pub pos: Location,
 }
 
 impl GetUseData {
-    pub fn new(body: Expr, name: &str) -> Self {
+    pub fn new(body: Expr, name: &str, pos: Location) -> Self {
         Self {
             body: body,
             name: name.to_string(),
+            pos: pos,
         }
     }
 }
 
-/// It's similar to an assignment, but it tries to assign value to
+/// It's similar to an assignment, but it tries to assign value to. Position: the field name
 #[derive(Clone, Debug, PartialEq)]
 pub struct SetUseData {
     pub body: Expr,
     pub name: String,
+    pub oper: AssignOper,
     pub value: Expr,
+    pub pos: Location,
 }
 
 impl SetUseData {
-    pub fn new(body: Expr, name: &str, value: Expr) -> Self {
+    pub fn new(body: Expr, name: &str, oper: AssignOper, value: Expr, pos: Location) -> Self {
         Self {
             body: body,
             name: name.to_string(),
+            oper: oper,
             value: value,
+            pos: pos,
         }
     }
 }
 
+/// `[a, b, c]`. Position: the left bracket
 #[derive(Clone, Debug, PartialEq)]
-pub struct SelfData {}
+pub struct ListData {
+    pub items: Args,
+    pub pos: Location,
+}
+
+/// `{k1: v1, k2: v2}`. Position: the left brace
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapData {
+    pub entries: Vec<(Expr, Expr)>,
+    pub pos: Location,
+}
+
+/// `body[index]`. Position: the left bracket
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexData {
+    pub body: Expr,
+    pub index: Expr,
+    pub pos: Location,
+}
+
+/// `body[index] = value` or compound assignment. Position: the operator
+#[derive(Clone, Debug, PartialEq)]
+pub struct SetIndexData {
+    pub body: Expr,
+    pub index: Expr,
+    pub oper: AssignOper,
+    pub value: Expr,
+    pub pos: Location,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfData {
+    /// `@` is resolved as a local variable of methods
+    pub var: VarUseData,
+    pub pos: Location,
+}
+
+/// `super.method`. Position: the `super` keyword
+#[derive(Clone, Debug, PartialEq)]
+pub struct SuperData {
+    pub method: String,
+    /// `super` is resolved as a local variable of methods of subclasses
+    pub var: VarUseData,
+    pub pos: Location,
+}
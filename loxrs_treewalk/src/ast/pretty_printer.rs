@@ -34,10 +34,27 @@ pub fn write_stmt(s: &mut String, indent: isize, stmt: &Stmt) {
             self::write_stmts(s, indent + 1, &while_.block.stmts);
             write!(s, ")").unwrap();
         }
+        For(ref for_) => {
+            write!(s, "(for {} ", for_.var).unwrap();
+            match for_.iter {
+                ForIter::Range(ref start, ref end) => {
+                    write!(s, "(range {} {})", start.pretty_print(), end.pretty_print()).unwrap()
+                }
+                ForIter::Each(ref iter) => write!(s, "{}", iter.pretty_print()).unwrap(),
+            }
+            write!(s, "\n").unwrap();
+            self::write_indent(s, indent + 1);
+            self::write_stmts(s, indent + 1, &for_.block.stmts);
+            write!(s, ")").unwrap();
+        }
+        Break(_) => write!(s, "(break)").unwrap(),
+        Continue(_) => write!(s, "(continue)").unwrap(),
         Fn(ref f) => write_fn(s, indent, f),
         Class(ref class) => {
             self::write_class(s, indent, class);
         }
+        Throw(ref throw) => write!(s, "(throw {})", throw.expr.pretty_print()).unwrap(),
+        Try(ref try_) => self::write_try(s, indent, try_),
     }
 }
 
@@ -70,6 +87,9 @@ pub fn write_fn(s: &mut String, indent: isize, f: &FnDeclArgs) {
 
 pub fn write_class(s: &mut String, indent: isize, class: &ClassDeclArgs) {
     write!(s, "(class {}", class.name,).unwrap();
+    if let Some(ref superclass) = class.superclass {
+        write!(s, " < {}", superclass.name).unwrap();
+    }
     for method in class.methods.iter() {
         write!(s, "\n").unwrap();
         write_indent(s, indent + 1);
@@ -78,6 +98,29 @@ pub fn write_class(s: &mut String, indent: isize, class: &ClassDeclArgs) {
     write!(s, ")").unwrap();
 }
 
+pub fn write_try(s: &mut String, indent: isize, try_: &TryArgs) {
+    writeln!(s, "(try").unwrap();
+    self::write_indent(s, indent + 1);
+    self::write_stmts(s, indent + 1, &try_.body.stmts);
+    if let Some(ref catch) = try_.catch {
+        writeln!(s).unwrap();
+        self::write_indent(s, indent + 1);
+        writeln!(s, "(catch {}", catch.var).unwrap();
+        self::write_indent(s, indent + 2);
+        self::write_stmts(s, indent + 2, &catch.block.stmts);
+        write!(s, ")").unwrap();
+    }
+    if let Some(ref finally) = try_.finally {
+        writeln!(s).unwrap();
+        self::write_indent(s, indent + 1);
+        writeln!(s, "(finally").unwrap();
+        self::write_indent(s, indent + 2);
+        self::write_stmts(s, indent + 2, &finally.stmts);
+        write!(s, ")").unwrap();
+    }
+    write!(s, ")").unwrap();
+}
+
 pub fn write_block(s: &mut String, indent: isize, stmts: &[Stmt]) {
     write!(s, "(block \n").unwrap();
     self::write_indent(s, indent);
@@ -149,7 +192,12 @@ impl PrettyPrint for Expr {
             Call(ref call) => call.pretty_print(),
             Get(ref get) => get.pretty_print(),
             Set(ref set) => set.pretty_print(),
+            List(ref list) => list.pretty_print(),
+            Map(ref map) => map.pretty_print(),
+            Index(ref index) => index.pretty_print(),
+            SetIndex(ref set) => set.pretty_print(),
             Self_(ref self_) => self_.pretty_print(),
+            Super(ref super_) => super_.pretty_print(),
         }
     }
 }
@@ -187,6 +235,20 @@ impl PrettyPrintHelper for BinaryOper {
     }
 }
 
+/// Compound assignments are suffixed to `assign` or `set`
+impl PrettyPrintHelper for AssignOper {
+    fn pretty_print_help(&self) -> &str {
+        use AssignOper::*;
+        match *self {
+            Equal => "",
+            Plus => "+=",
+            Minus => "-=",
+            Mul => "*=",
+            Div => "/=",
+        }
+    }
+}
+
 impl PrettyPrintHelper for LogicOper {
     fn pretty_print_help(&self) -> &str {
         use LogicOper::*;
@@ -199,7 +261,13 @@ impl PrettyPrintHelper for LogicOper {
 
 impl PrettyPrint for LiteralData {
     fn pretty_print(&self) -> String {
-        use LiteralData::*;
+        self.value.pretty_print()
+    }
+}
+
+impl PrettyPrint for LiteralValue {
+    fn pretty_print(&self) -> String {
+        use LiteralValue::*;
         match *self {
             Nil => "Nil".into(),
             Bool(b) => {
@@ -256,7 +324,8 @@ impl PrettyPrint for GroupData {
 impl PrettyPrint for AssignData {
     fn pretty_print(&self) -> String {
         format!(
-            "(assign \"{}\" {})",
+            "(assign{} \"{}\" {})",
+            self.oper.pretty_print_help(),
             self.assigned.name,
             self.expr.pretty_print()
         )
@@ -282,7 +351,8 @@ impl PrettyPrint for GetUseData {
 impl PrettyPrint for SetUseData {
     fn pretty_print(&self) -> String {
         format!(
-            "(set {} {} {})",
+            "(set{} {} {} {})",
+            self.oper.pretty_print_help(),
             self.body.pretty_print(),
             self.name,
             self.value.pretty_print(),
@@ -290,6 +360,50 @@ impl PrettyPrint for SetUseData {
     }
 }
 
+impl PrettyPrint for ListData {
+    fn pretty_print(&self) -> String {
+        format!(
+            "(list {})",
+            self::pretty_vec(self.items.iter().map(|expr| expr.pretty_print()))
+        )
+    }
+}
+
+impl PrettyPrint for MapData {
+    fn pretty_print(&self) -> String {
+        format!(
+            "(map {})",
+            self::pretty_vec(self.entries.iter().map(|(key, value)| format!(
+                "({} {})",
+                key.pretty_print(),
+                value.pretty_print()
+            )))
+        )
+    }
+}
+
+impl PrettyPrint for IndexData {
+    fn pretty_print(&self) -> String {
+        format!(
+            "(index {} {})",
+            self.body.pretty_print(),
+            self.index.pretty_print()
+        )
+    }
+}
+
+impl PrettyPrint for SetIndexData {
+    fn pretty_print(&self) -> String {
+        format!(
+            "(set-index{} {} {} {})",
+            self.oper.pretty_print_help(),
+            self.body.pretty_print(),
+            self.index.pretty_print(),
+            self.value.pretty_print(),
+        )
+    }
+}
+
 // statements
 
 impl PrettyPrint for SelfData {
@@ -298,6 +412,12 @@ impl PrettyPrint for SelfData {
     }
 }
 
+impl PrettyPrint for SuperData {
+    fn pretty_print(&self) -> String {
+        format!("(super {})", self.method)
+    }
+}
+
 impl PrettyPrint for BlockArgs {
     fn pretty_print(&self) -> String {
         self.stmts
@@ -316,12 +436,15 @@ mod test {
     fn test_in_part_5() {
         use crate::ast::expr::*;
         use crate::ast::pretty_printer::*;
+        use crate::lexer::token::Location;
+        let pos = Location::initial();
         println!(
             "{}",
             Expr::binary(
-                Expr::unary(UnaryOper::Minus, Expr::literal(123.0.into())),
+                Expr::unary(UnaryOper::Minus, Expr::literal(123.0.into(), pos), pos),
                 BinaryOper::Mul,
-                Expr::group(Expr::literal(45.67.into())),
+                Expr::group(Expr::literal(45.67.into(), pos), pos),
+                pos,
             )
             .pretty_print()
         );
@@ -1,4 +1,5 @@
-use crate::ast::expr::Expr;
+use crate::ast::expr::{Expr, VarUseData};
+use crate::lexer::token::Location;
 use std::rc::Rc;
 
 // TODO: use proper places for function definitions
@@ -16,9 +17,16 @@ pub enum Stmt {
     If(Box<IfArgs>),
     Return(Return),
     While(WhileArgs),
+    For(Box<ForArgs>),
+    /// Exits the innermost loop. Position: the `break` keyword
+    Break(Location),
+    /// Goes to the next iteration of the innermost loop. Position: the `continue` keyword
+    Continue(Location),
     /// A code block except a body of a function
     Block(BlockArgs),
     Class(ClassDeclArgs),
+    Throw(ThrowArgs),
+    Try(Box<TryArgs>),
 }
 
 impl Stmt {
@@ -26,37 +34,77 @@ impl Stmt {
         Stmt::Expr(expr)
     }
 
-    pub fn print(expr: Expr) -> Self {
-        Stmt::Print(PrintArgs { expr: expr })
+    pub fn print(expr: Expr, pos: Location) -> Self {
+        Stmt::Print(PrintArgs {
+            expr: expr,
+            pos: pos,
+        })
     }
 
-    pub fn var_dec(name: String, init: Expr) -> Self {
-        Stmt::Var(VarDeclArgs::new(name, init))
+    pub fn var_dec(name: String, init: Expr, pos: Location) -> Self {
+        Stmt::Var(VarDeclArgs::new(name, init, pos))
     }
 
-    pub fn if_then_else(condition: Expr, then: BlockArgs, else_: Option<ElseBranch>) -> Self {
-        Stmt::If(Box::new(IfArgs {
-            condition: condition,
-            if_true: then,
-            if_false: else_,
-        }))
+    pub fn if_then_else(
+        condition: Expr,
+        then: BlockArgs,
+        else_: Option<ElseBranch>,
+        pos: Location,
+    ) -> Self {
+        Stmt::If(Box::new(IfArgs::new(condition, then, else_, pos)))
     }
 
-    pub fn block(stmts: Vec<Stmt>) -> Self {
-        Stmt::Block(BlockArgs { stmts: stmts })
+    pub fn block(stmts: Vec<Stmt>, pos: Location) -> Self {
+        Stmt::Block(BlockArgs {
+            stmts: stmts,
+            pos: pos,
+        })
     }
 
     /// Even if a function returns nothing, it returns `Some(LoxObj::Nul)` internally
-    pub fn return_(expr: Expr) -> Self {
-        Stmt::Return(Return { expr: expr })
+    pub fn return_(expr: Expr, pos: Location) -> Self {
+        Stmt::Return(Return {
+            expr: expr,
+            pos: pos,
+        })
     }
 
-    pub fn while_(condition: Expr, block: BlockArgs) -> Self {
+    pub fn while_(condition: Expr, block: BlockArgs, pos: Location) -> Self {
         Stmt::While(WhileArgs {
             condition: condition,
             block: block,
+            pos: pos,
         })
     }
+
+    pub fn for_(var: String, iter: ForIter, block: BlockArgs, pos: Location) -> Self {
+        Stmt::For(Box::new(ForArgs {
+            var: var,
+            iter: iter,
+            block: block,
+            pos: pos,
+        }))
+    }
+
+    /// Source position of the token that starts (or names) the statement
+    pub fn pos(&self) -> Location {
+        use Stmt::*;
+        match self {
+            Expr(expr) => expr.pos(),
+            Fn(f) => f.pos,
+            Print(print) => print.pos,
+            Var(var) => var.pos,
+            If(if_) => if_.pos,
+            Return(ret) => ret.pos,
+            While(while_) => while_.pos,
+            For(for_) => for_.pos,
+            Break(pos) | Continue(pos) => *pos,
+            Block(block) => block.pos,
+            Class(class) => class.pos,
+            Throw(throw) => throw.pos,
+            Try(try_) => try_.pos,
+        }
+    }
 }
 
 impl From<PrintArgs> for Stmt {
@@ -71,32 +119,44 @@ impl From<VarDeclArgs> for Stmt {
     }
 }
 
+/// Position: the `print` keyword
 #[derive(Clone, Debug, PartialEq)]
 pub struct PrintArgs {
     // pub message: String,
     pub expr: Expr,
+    pub pos: Location,
 }
 
+/// Position: the variable name
 #[derive(Clone, Debug, PartialEq)]
 pub struct VarDeclArgs {
     pub name: String,
     pub init: Expr,
+    pub pos: Location,
 }
 
 // split into IfThen and IfThenElse
+/// Position: the `if` keyword
 #[derive(Clone, Debug, PartialEq)]
 pub struct IfArgs {
     pub condition: Expr,
     pub if_true: BlockArgs,
     pub if_false: Option<ElseBranch>,
+    pub pos: Location,
 }
 
 impl IfArgs {
-    pub fn new(cond: Expr, if_true: BlockArgs, if_false: Option<ElseBranch>) -> Self {
+    pub fn new(
+        cond: Expr,
+        if_true: BlockArgs,
+        if_false: Option<ElseBranch>,
+        pos: Location,
+    ) -> Self {
         Self {
             condition: cond,
             if_true: if_true,
             if_false: if_false,
+            pos: pos,
         }
     }
 }
@@ -115,20 +175,20 @@ impl ElseBranch {
 
 impl VarDeclArgs {
     /// Unlike the original Lox language, loxrs always requires initializer for declarations
-    pub fn new(name: String, init: Expr) -> Self {
+    pub fn new(name: String, init: Expr, pos: Location) -> Self {
         Self {
             name: name,
             init: init,
+            pos: pos,
         }
     }
 }
 
-/// A separated code block, not a body of a function
-///
-/// Newtype pattern for `Vec<Stmt>>`.
+/// A separated code block, not a body of a function. Position: the left brace
 #[derive(Clone, Debug, PartialEq)]
 pub struct BlockArgs {
     pub stmts: Vec<Stmt>,
+    pub pos: Location,
 }
 
 impl BlockArgs {
@@ -138,47 +198,113 @@ impl BlockArgs {
 }
 
 /// Even if a function returns nothing, it returns `Some(LoxObj::Nul)` internally
+///
+/// Position: the `return` keyword
 #[derive(Clone, Debug, PartialEq)]
 pub struct Return {
     pub expr: Expr,
+    pub pos: Location,
 }
 
+/// Position: the `while` keyword
 #[derive(Clone, Debug, PartialEq)]
 pub struct WhileArgs {
     pub condition: Expr,
     pub block: BlockArgs,
+    pub pos: Location,
+}
+
+/// `for var in iter { .. }`. Position: the `for` keyword
+///
+/// The loop variable is defined in a new scope for each iteration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForArgs {
+    pub var: String,
+    pub iter: ForIter,
+    pub block: BlockArgs,
+    pub pos: Location,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ForIter {
+    /// `start..end`, excluding the end
+    Range(Expr, Expr),
+    /// Items of an iterable object
+    Each(Expr),
+}
+
+/// `throw expr;`. Position: the `throw` keyword
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThrowArgs {
+    pub expr: Expr,
+    pub pos: Location,
+}
+
+/// `try { .. } catch (e) { .. } finally { .. }`. Position: the `try` keyword
+///
+/// Either `catch` or `finally` (or both) is given.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TryArgs {
+    pub body: BlockArgs,
+    pub catch: Option<CatchArgs>,
+    pub finally: Option<BlockArgs>,
+    pub pos: Location,
+}
+
+/// `catch (e) { .. }`. The error is defined in the scope of the block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CatchArgs {
+    pub var: String,
+    pub block: BlockArgs,
 }
 
-/// Function definition translated to AST
+/// Function definition translated to AST. Position: the function name
 #[derive(Clone, Debug, PartialEq)]
 pub struct FnDeclArgs {
     pub name: String,
     pub body: FnBody,
     pub params: Params,
+    pub pos: Location,
 }
 
 impl FnDeclArgs {
-    pub fn new(name: String, body: Rc<Vec<Stmt>>, params: impl Into<Params>) -> Self {
+    pub fn new(
+        name: String,
+        body: Rc<Vec<Stmt>>,
+        params: impl Into<Params>,
+        pos: Location,
+    ) -> Self {
         Self {
             name: name,
             body: body,
             params: params.into(),
+            pos: pos,
         }
     }
 }
 
-/// In Lox, fields are dynamically added
+/// In Lox, fields are dynamically added. Position: the class name
 #[derive(Clone, Debug, PartialEq)]
 pub struct ClassDeclArgs {
     pub name: String,
+    /// `class B < A`; the superclass is looked up as a variable
+    pub superclass: Option<VarUseData>,
     pub methods: Vec<FnDeclArgs>,
+    pub pos: Location,
 }
 
 impl ClassDeclArgs {
-    pub fn new(name: String, methods: Vec<FnDeclArgs>) -> Self {
+    pub fn new(
+        name: String,
+        superclass: Option<VarUseData>,
+        methods: Vec<FnDeclArgs>,
+        pos: Location,
+    ) -> Self {
         Self {
             name: name,
+            superclass: superclass,
             methods: methods,
+            pos: pos,
         }
     }
 }
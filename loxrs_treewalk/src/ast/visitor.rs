@@ -1,6 +1,7 @@
 //! Automates double dispatches reducing `switch`
 
 use crate::ast::{expr::*, stmt::*};
+use crate::lexer::token::Location;
 
 /// Automates double dispatches reducing `match`
 pub trait ExprVisitor<T> {
@@ -19,7 +20,12 @@ pub trait ExprVisitor<T> {
             Call(call) => self.visit_call_expr(call.as_ref()),
             Get(get) => self.visit_get_expr(get.as_ref()),
             Set(set) => self.visit_set_expr(set.as_ref()),
+            List(list) => self.visit_list_expr(list),
+            Map(map) => self.visit_map_expr(map),
+            Index(index) => self.visit_index_expr(index.as_ref()),
+            SetIndex(set) => self.visit_set_index_expr(set.as_ref()),
             Self_(self_) => self.visit_self_expr(self_),
+            Super(super_) => self.visit_super_expr(super_),
         }
     }
     // sub functions to one of the variants of `Expr`
@@ -32,7 +38,12 @@ pub trait ExprVisitor<T> {
     fn visit_call_expr(&mut self, call: &CallData) -> T;
     fn visit_get_expr(&mut self, get: &GetUseData) -> T;
     fn visit_set_expr(&mut self, set: &SetUseData) -> T;
+    fn visit_list_expr(&mut self, list: &ListData) -> T;
+    fn visit_map_expr(&mut self, map: &MapData) -> T;
+    fn visit_index_expr(&mut self, index: &IndexData) -> T;
+    fn visit_set_index_expr(&mut self, set: &SetIndexData) -> T;
     fn visit_self_expr(&mut self, self_: &SelfData) -> T;
+    fn visit_super_expr(&mut self, super_: &SuperData) -> T;
 }
 
 /// Automates double dispatches
@@ -48,8 +59,13 @@ pub trait StmtVisitor<T> {
             Block(block) => self.visit_block_stmt(block.stmts.as_ref()),
             Return(ret) => self.visit_return_stmt(ret),
             While(while_) => self.visit_while_stmt(while_),
+            For(for_) => self.visit_for_stmt(for_),
+            Break(pos) => self.visit_break_stmt(*pos),
+            Continue(pos) => self.visit_continue_stmt(*pos),
             Fn(f) => self.visit_fn_decl(f),
             Class(c) => self.visit_class_decl(c),
+            Throw(throw) => self.visit_throw_stmt(throw),
+            Try(try_) => self.visit_try_stmt(try_),
         }
     }
     fn visit_var_decl(&mut self, var: &VarDeclArgs) -> T;
@@ -63,7 +79,12 @@ pub trait StmtVisitor<T> {
     fn visit_block_stmt(&mut self, stmts: &Vec<Stmt>) -> T;
     fn visit_return_stmt(&mut self, ret: &Return) -> T;
     fn visit_while_stmt(&mut self, while_: &WhileArgs) -> T;
+    fn visit_for_stmt(&mut self, for_: &ForArgs) -> T;
+    fn visit_break_stmt(&mut self, pos: Location) -> T;
+    fn visit_continue_stmt(&mut self, pos: Location) -> T;
     // TODO: disable clock as a variable name? (or distinguish two scopes like Lisp 2?)
     fn visit_fn_decl(&mut self, f: &FnDeclArgs) -> T;
     fn visit_class_decl(&mut self, c: &ClassDeclArgs) -> T;
+    fn visit_throw_stmt(&mut self, throw: &ThrowArgs) -> T;
+    fn visit_try_stmt(&mut self, try_: &TryArgs) -> T;
 }
@@ -5,13 +5,16 @@ use anyhow::{anyhow, Context, Error};
 use std::{
     env, fs,
     io::{self, BufRead, BufWriter, Write},
+    path::Path,
 };
 
 use crate::{
     analizer::resolver::Resolver,
     ast::{stmt::Stmt, PrettyPrint},
+    diagnostics::Emitter,
+    golden,
     lexer::{parser::Parser, scanner::Scanner},
-    runtime::{obj::LoxObj, Interpreter /*Result*/},
+    runtime::{obj::LoxObj, on_script_stack, Interpreter /*Result*/},
 };
 
 // --------------------------------------------------------------------------------
@@ -36,15 +39,26 @@ pub struct RunContext {
 pub struct Cli {
     pub cx: RunContext,
     pub run_file: Option<String>,
+    /// Directory of golden-file tests (`loxrs test <dir>`)
+    pub test_dir: Option<String>,
 }
 
 impl Cli {
     fn parse_args(&mut self) -> Result<()> {
         let args: Vec<String> = env::args().collect();
-        for arg in args.iter().skip(1) {
+        let mut args = args.iter().skip(1).peekable();
+        let is_test = args.peek().map(|arg| arg.as_str()) == Some("test");
+        if is_test {
+            args.next();
+        }
+        for arg in args {
             self.parse_arg(arg.as_str())?;
         }
-        self.cx.is_repl = self.run_file.is_none();
+        if is_test {
+            let dir = self.run_file.take();
+            self.test_dir = Some(dir.ok_or_else(|| anyhow!("Given no directory to test"))?);
+        }
+        self.cx.is_repl = self.run_file.is_none() && self.test_dir.is_none();
         Ok(())
     }
 
@@ -61,13 +75,18 @@ impl Cli {
         Ok(())
     }
 
+    /// Runs on a thread with a large stack so that scripts can nest calls deeply
     pub fn run(&self) -> Result<()> {
-        if let Some(file) = self.run_file.as_ref() {
-            self::run_file(file, &self.cx)?;
-        } else {
-            self::run_repl(&self.cx)?;
-        }
-        Ok(())
+        on_script_stack(|| {
+            if let Some(dir) = self.test_dir.as_ref() {
+                self::run_tests(dir)?;
+            } else if let Some(file) = self.run_file.as_ref() {
+                self::run_file(file, &self.cx)?;
+            } else {
+                self::run_repl(&self.cx)?;
+            }
+            Ok(())
+        })
     }
 }
 
@@ -75,14 +94,24 @@ impl Cli {
 // Running
 
 // TODO: buffering for reading source files
+/// Runs a file. Call it in [`on_script_stack`] as [`Cli::run`] does.
 pub fn run_file(path: &str, cx: &RunContext) -> Result<LoxObj> {
     let src = fs::read_to_string(path).map_err(Error::msg)?;
     let mut interpreter = Interpreter::new();
-    self::run_string(&src, cx, &mut interpreter)
+    self::run_string(path, &src, cx, &mut interpreter)
 }
 
 /// Returns a `Result` of the interpretation if parse & Resolving succeeded
-pub fn run_string(source: &str, cx: &RunContext, interpreter: &mut Interpreter) -> Result<LoxObj> {
+///
+/// `path` is only used for reporting errors.
+pub fn run_string(
+    path: &str,
+    source: &str,
+    cx: &RunContext,
+    interpreter: &mut Interpreter,
+) -> Result<LoxObj> {
+    let mut emitter = Emitter::stderr(path, source);
+
     // scan
     let (tks, scan_errors) = Scanner::new(&source).scan();
 
@@ -90,7 +119,9 @@ pub fn run_string(source: &str, cx: &RunContext, interpreter: &mut Interpreter)
         self::print_all_debug("====== tokens =====", &tks);
     }
     if scan_errors.len() > 0 {
-        self::print_all_debug("====== scan errors =====", &scan_errors);
+        for err in scan_errors.iter() {
+            emitter.emit(err)?;
+        }
         return Err(anyhow!("=> failed to scan"));
     }
 
@@ -107,42 +138,79 @@ pub fn run_string(source: &str, cx: &RunContext, interpreter: &mut Interpreter)
         );
     }
     if parse_errors.len() > 0 {
-        self::print_all_debug("===== parse errors =====", &parse_errors);
+        for err in parse_errors.iter() {
+            emitter.emit(err)?;
+        }
         return Err(anyhow!("=> failed to parse"));
     }
 
     // analizing
-    let mut resolver = Resolver::new(&mut interpreter.caches);
-    resolver.resolve_stmts(&mut stmts).map_err(Error::msg)?;
+    let mut resolver = Resolver::new();
+    if let Err(why) = resolver.resolve_stmts(&mut stmts) {
+        emitter.emit(&why)?;
+        return Err(anyhow!("=> failed to resolve"));
+    }
 
-    self::interpret(interpreter, &mut stmts, cx)
+    self::interpret(interpreter, &mut stmts, cx, &mut emitter)
 }
 
 pub fn interpret(
     interpreter: &mut Interpreter,
     stmts: &mut [Stmt],
     cx: &RunContext,
+    emitter: &mut Emitter,
 ) -> Result<LoxObj> {
     if !cx.is_repl && cx.is_debug {
         println!("====== interpretations =====");
     }
     let mut res = Ok(None);
-    for (i, stmt) in stmts.iter().enumerate() {
+    for stmt in stmts.iter() {
         res = interpreter.interpret(stmt);
         if let Err(why) = res {
             if !cx.is_repl && cx.is_debug {
                 eprintln!("\n====== runtime errors =====");
             }
-            eprintln!("at {}, {:?}", i, why);
+            emitter.emit(&why)?;
             return Err(why).map_err(Error::msg);
         }
     }
     Ok(res.unwrap().unwrap_or(LoxObj::nil()))
 }
 
+// --------------------------------------------------------------------------------
+// Testing
+
+/// Runs golden-file tests in a directory, printing the results
+pub fn run_tests(dir: &str) -> Result<()> {
+    let report = golden::check_dir(Path::new(dir))
+        .with_context(|| format!("when reading test directory {}", dir))?;
+
+    for path in report.passed.iter() {
+        println!("PASS {}", path.display());
+    }
+    for (path, failures) in report.failed.iter() {
+        println!("FAIL {}", path.display());
+        for failure in failures.iter() {
+            println!("    {}", failure);
+        }
+    }
+    println!(
+        "\n{} passed, {} failed",
+        report.passed.len(),
+        report.failed.len()
+    );
+
+    if report.failed.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("=> {} test(s) failed", report.failed.len()))
+    }
+}
+
 // --------------------------------------------------------------------------------
 // REPL
 
+/// Runs the REPL. Call it in [`on_script_stack`] as [`Cli::run`] does.
 pub fn run_repl(cx: &RunContext) -> Result<()> {
     println!("Entered loxrs REPL (press q<Enter> or Ctrl-c to quit)");
     let prompt = "> ";
@@ -167,7 +235,7 @@ pub fn run_repl(cx: &RunContext) -> Result<()> {
             "q" | "quit" => {
                 break;
             }
-            line => match self::run_string(line, cx, &mut interpreter) {
+            line => match self::run_string("<repl>", line, cx, &mut interpreter) {
                 Ok(obj) => println!("{:?}", obj),
                 Err(why) => println!("{:?}", why),
             },
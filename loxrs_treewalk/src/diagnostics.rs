@@ -0,0 +1,339 @@
+//! Human-readable error reports with source snippets, in the style of `rustc`
+//!
+//! ```none
+//! error: mismatched type
+//!  --> examples/fn.lox:3:12
+//!   |
+//! 3 |   return a + "s";
+//!   |            ^
+//! ```
+
+use std::io::{self, IsTerminal};
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::{
+    analizer::resolver::SemantcicError,
+    lexer::{
+        parser::ParseError,
+        scanner::{ScanError, Scanner},
+        token::Location,
+    },
+    runtime::{Frame, RuntimeError},
+};
+
+/// Backtraces longer than this are shown partially
+const MAX_FRAMES: usize = 20;
+
+/// An error report: a primary message at some source position, optional notes and a backtrace
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub msg: String,
+    pub pos: Option<Location>,
+    pub notes: Vec<String>,
+    /// Function calls, innermost first
+    pub frames: Vec<Frame>,
+}
+
+impl Diagnostic {
+    pub fn new(msg: impl Into<String>, pos: Option<Location>) -> Self {
+        Self {
+            msg: msg.into(),
+            pos: pos,
+            notes: Vec::new(),
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl From<&ScanError> for Diagnostic {
+    fn from(err: &ScanError) -> Self {
+        Diagnostic::new(err.to_string(), Some(err.pos()))
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Self {
+        let diag = Diagnostic::new(err.to_string(), Some(err.pos()));
+        match err {
+            ParseError::NotAssignable(_) => {
+                diag.note("only variables and fields of instances can be assigned to")
+            }
+            _ => diag,
+        }
+    }
+}
+
+impl From<&SemantcicError> for Diagnostic {
+    fn from(err: &SemantcicError) -> Self {
+        let diag = Diagnostic::new(err.to_string(), Some(err.pos()));
+        use SemantcicError::*;
+        match err {
+            RecursiveVariableDeclaration(..) => {
+                diag.note("a local variable can't be read in its own initializer")
+            }
+            ReturnFromNonFunction(_) => diag.note("`return` is only allowed in functions"),
+            BreakOutsideLoop(_) | ContinueOutsideLoop(_) => {
+                diag.note("`break` and `continue` are only allowed in loops")
+            }
+            UseOfSelfOutsideMethod(_) => diag.note("`@` is only available in methods"),
+            UseOfSuperOutsideSubclass(_) => {
+                diag.note("`super` is only available in methods of a class with a superclass")
+            }
+            _ => diag,
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(err: &RuntimeError) -> Self {
+        let mut diag = Diagnostic::new(err.to_string(), err.pos);
+        diag.frames = err.backtrace();
+        diag
+    }
+}
+
+/// Renders `Diagnostic`s on a source file
+pub struct Emitter<'a> {
+    path: &'a str,
+    src: &'a str,
+    out: StandardStream,
+}
+
+impl<'a> Emitter<'a> {
+    /// Writes to stderr, colored when stdout is a terminal. Piping the output of a script turns
+    /// the color off along with it. Use `Emitter::stderr_with_color` to choose otherwise.
+    pub fn stderr(path: &'a str, src: &'a str) -> Self {
+        let color = if io::stdout().is_terminal() {
+            ColorChoice::Auto
+        } else {
+            ColorChoice::Never
+        };
+        Self::stderr_with_color(path, src, color)
+    }
+
+    /// Writes to stderr, colored as chosen
+    pub fn stderr_with_color(path: &'a str, src: &'a str, color: ColorChoice) -> Self {
+        Self {
+            path: path,
+            src: src,
+            out: StandardStream::stderr(color),
+        }
+    }
+
+    pub fn emit(&mut self, diag: impl Into<Diagnostic>) -> io::Result<()> {
+        self::render(&mut self.out, self.path, self.src, &diag.into())
+    }
+}
+
+/// Renders a `Diagnostic` with the source line it points to
+pub fn render(
+    out: &mut impl WriteColor,
+    path: &str,
+    src: &str,
+    diag: &Diagnostic,
+) -> io::Result<()> {
+    let mut blue = ColorSpec::new();
+    blue.set_fg(Some(Color::Blue)).set_bold(true);
+    let mut red = ColorSpec::new();
+    red.set_fg(Some(Color::Red)).set_bold(true);
+
+    // error: <msg>
+    out.set_color(&red)?;
+    write!(out, "error")?;
+    out.set_color(ColorSpec::new().set_bold(true))?;
+    writeln!(out, ": {}", diag.msg)?;
+    out.reset()?;
+
+    // the line may be missing when the position comes from a previous REPL input
+    let (pos, line) = match diag.pos {
+        Some(pos) => match src.lines().nth(pos.ln() - 1) {
+            Some(line) => (pos, line),
+            None => {
+                self::write_arrow(out, &blue, 0, &format!("{}:{}", path, pos))?;
+                return self::write_footer(out, &blue, 0, path, diag);
+            }
+        },
+        None => {
+            self::write_arrow(out, &blue, 0, path)?;
+            return self::write_footer(out, &blue, 0, path, diag);
+        }
+    };
+    let width = pos.ln().to_string().len();
+
+    //  --> path:ln:col
+    //   |
+    self::write_arrow(out, &blue, width, &format!("{}:{}", path, pos))?;
+    self::write_gutter(out, &blue, width, "")?;
+    writeln!(out)?;
+
+    // ln | <source line>
+    self::write_gutter(out, &blue, width, &pos.ln().to_string())?;
+    writeln!(out, " {}", line)?;
+
+    //    |    ^^^
+    self::write_gutter(out, &blue, width, "")?;
+    // keep tabs so that the caret is aligned
+    let pad = line
+        .chars()
+        .take(pos.col() - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    write!(out, " {}", pad)?;
+    out.set_color(&red)?;
+    writeln!(out, "{}", "^".repeat(self::span_len(line, pos.col())))?;
+    out.reset()?;
+
+    self::write_footer(out, &blue, width, path, diag)
+}
+
+/// Notes and the backtrace
+fn write_footer(
+    out: &mut impl WriteColor,
+    color: &ColorSpec,
+    width: usize,
+    path: &str,
+    diag: &Diagnostic,
+) -> io::Result<()> {
+    self::write_notes(out, color, width, &diag.notes)?;
+    self::write_frames(out, width, path, &diag.frames)
+}
+
+/// ` --> <location>`
+fn write_arrow(
+    out: &mut impl WriteColor,
+    color: &ColorSpec,
+    width: usize,
+    location: &str,
+) -> io::Result<()> {
+    out.set_color(color)?;
+    write!(out, "{:width$}--> ", "", width = width)?;
+    out.reset()?;
+    writeln!(out, "{}", location)
+}
+
+/// `<ln> |`
+fn write_gutter(
+    out: &mut impl WriteColor,
+    color: &ColorSpec,
+    width: usize,
+    ln: &str,
+) -> io::Result<()> {
+    out.set_color(color)?;
+    write!(out, "{:>width$} |", ln, width = width)?;
+    out.reset()
+}
+
+/// `  = note: <note>`
+fn write_notes(
+    out: &mut impl WriteColor,
+    color: &ColorSpec,
+    width: usize,
+    notes: &[String],
+) -> io::Result<()> {
+    for note in notes.iter() {
+        out.set_color(color)?;
+        write!(out, "{:width$} = ", "", width = width)?;
+        out.reset()?;
+        writeln!(out, "note: {}", note)?;
+    }
+    Ok(())
+}
+
+/// `   at <name> (<location>)`, omitting the middle of a long backtrace
+fn write_frames(
+    out: &mut impl WriteColor,
+    width: usize,
+    path: &str,
+    frames: &[Frame],
+) -> io::Result<()> {
+    let half = MAX_FRAMES / 2;
+    for (i, frame) in frames.iter().enumerate() {
+        if frames.len() > MAX_FRAMES && i >= half && i < frames.len() - half {
+            if i == half {
+                let n = frames.len() - MAX_FRAMES;
+                writeln!(out, "{:width$}   .. {} more frames", "", n, width = width)?;
+            }
+            continue;
+        }
+        match frame.pos {
+            Some(pos) => writeln!(
+                out,
+                "{:width$}   at {} ({}:{})",
+                "",
+                frame.name,
+                path,
+                pos,
+                width = width
+            )?,
+            None => writeln!(out, "{:width$}   at {}", "", frame.name, width = width)?,
+        }
+    }
+    Ok(())
+}
+
+/// Length of the token starting at the column (or 1 if there's no such token)
+fn span_len(line: &str, col: usize) -> usize {
+    let (tks, _errors) = Scanner::new(line).scan();
+    tks.iter()
+        .find(|tk| tk.pos == Location::new(1, col))
+        .map(|tk| tk.lexeme.chars().count())
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, Diagnostic, Frame};
+    use crate::lexer::token::Location;
+    use termcolor::NoColor;
+
+    fn render_to_string(src: &str, diag: &Diagnostic) -> String {
+        let mut out = NoColor::new(Vec::new());
+        render(&mut out, "test.lox", src, diag).unwrap();
+        String::from_utf8(out.into_inner()).unwrap()
+    }
+
+    #[test]
+    fn underline_token() {
+        let src = "var x = 1;\nprint undefined_var + 2;\n";
+        let diag = Diagnostic::new("looked up undefined variable", Some(Location::new(2, 7)))
+            .note("declare it first");
+        let expected = r#"error: looked up undefined variable
+ --> test.lox:2:7
+  |
+2 | print undefined_var + 2;
+  |       ^^^^^^^^^^^^^
+  = note: declare it first
+"#;
+        assert_eq!(render_to_string(src, &diag), expected);
+    }
+
+    #[test]
+    fn backtrace() {
+        let src = "fn f() {\n    return nil + 1;\n}\nf();\n";
+        let mut diag = Diagnostic::new("mismatched type", Some(Location::new(2, 16)));
+        diag.frames = vec![
+            Frame {
+                name: "f".to_string(),
+                pos: Some(Location::new(2, 16)),
+            },
+            Frame {
+                name: "script".to_string(),
+                pos: Some(Location::new(4, 2)),
+            },
+        ];
+        let expected = r#"error: mismatched type
+ --> test.lox:2:16
+  |
+2 |     return nil + 1;
+  |                ^
+    at f (test.lox:2:16)
+    at script (test.lox:4:2)
+"#;
+        assert_eq!(render_to_string(src, &diag), expected);
+    }
+}
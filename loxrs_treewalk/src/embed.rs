@@ -0,0 +1,238 @@
+//! Embedding API: evaluate Lox source from Rust and read results back
+//!
+//! ```no_run
+//! use loxrs_treewalk::Lox;
+//! use std::convert::TryFrom;
+//!
+//! let mut lox = Lox::new();
+//! lox.set("width", 3.0);
+//! lox.eval("fn area(height) { return width * height; }").unwrap();
+//! let area = lox.call("area", &[4.0.into()]).unwrap();
+//! assert_eq!(f64::try_from(area).unwrap(), 12.0);
+//! ```
+
+use std::io::Write;
+use thiserror::Error;
+
+use crate::{
+    analizer::resolver::Resolver,
+    ast::stmt::Stmt,
+    lexer::{parser::Parser, scanner::Scanner},
+    runtime::{obj::LoxObj, Interpreter, RuntimeErrorKind},
+};
+
+pub use crate::{
+    analizer::resolver::SemantcicError, lexer::parser::ParseError, lexer::scanner::ScanError,
+    runtime::RuntimeError,
+};
+
+/// Error from any phase of evaluation
+#[derive(Debug, Error)]
+pub enum LoxError {
+    #[error("failed to scan: {}", self::join(.0))]
+    Scan(Vec<ScanError>),
+    #[error("failed to parse: {}", self::join(.0))]
+    Parse(Vec<ParseError>),
+    #[error("failed to resolve: {0}")]
+    Resolve(SemantcicError),
+    #[error("runtime error: {0}")]
+    Runtime(#[from] RuntimeError),
+}
+
+fn join<T: ToString>(errors: &[T]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+pub type Result<T> = ::std::result::Result<T, LoxError>;
+
+/// An interpreter session which keeps global variables across evaluations
+///
+/// ```
+/// use loxrs_treewalk::{Lox, LoxValue};
+/// use std::convert::TryFrom;
+///
+/// fn main() -> Result<(), Box<dyn std::error::Error>> {
+///     let mut lox = Lox::new();
+///     lox.interpreter().define_native("twice", 1, |_interpreter, args| {
+///         let n = args[0].as_num().unwrap_or(0.0);
+///         Ok(LoxValue::Number(n * 2.0).into())
+///     })?;
+///     lox.set("x", 21.0);
+///     let y = lox.eval("twice(x);")?;
+///     assert_eq!(f64::try_from(y)?, 42.0);
+///     Ok(())
+/// }
+/// ```
+#[derive(Default)]
+pub struct Lox {
+    interpreter: Interpreter,
+}
+
+impl Lox {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    /// Creates a session which prints to the given writer instead of stdout
+    pub fn with_output(out: Box<dyn Write>) -> Self {
+        Self {
+            interpreter: Interpreter::with_output(out),
+        }
+    }
+
+    /// The underlying interpreter, e.g. to define native functions
+    pub fn interpreter(&mut self) -> &mut Interpreter {
+        &mut self.interpreter
+    }
+
+    /// Runs a program and returns the value of the last expression statement (or `nil`)
+    pub fn eval(&mut self, src: &str) -> Result<LoxObj> {
+        let stmts = self.compile(src)?;
+        let (last, init) = match stmts.split_last() {
+            Some(split) => split,
+            None => return Ok(LoxObj::nil()),
+        };
+        for stmt in init.iter() {
+            self.interpreter.interpret(stmt)?;
+        }
+        match last {
+            Stmt::Expr(expr) => Ok(self.interpreter.interpret_expr(expr)?),
+            stmt => {
+                self.interpreter.interpret(stmt)?;
+                Ok(LoxObj::nil())
+            }
+        }
+    }
+
+    /// Scans, parses and resolves a program
+    fn compile(&mut self, src: &str) -> Result<Vec<Stmt>> {
+        let (tks, scan_errors) = Scanner::new(src).scan();
+        if !scan_errors.is_empty() {
+            return Err(LoxError::Scan(scan_errors));
+        }
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        if !parse_errors.is_empty() {
+            return Err(LoxError::Parse(parse_errors));
+        }
+        Resolver::new()
+            .resolve_stmts(&stmts)
+            .map_err(LoxError::Resolve)?;
+        Ok(stmts)
+    }
+
+    /// Looks up a global variable
+    pub fn get(&self, name: &str) -> Option<LoxObj> {
+        self.interpreter.get_global(name)
+    }
+
+    /// Defines a global variable or overwrites it if it exists
+    pub fn set(&mut self, name: &str, value: impl Into<LoxObj>) {
+        self.interpreter.set_global(name, value.into());
+    }
+
+    /// Calls a global function (or a class as a constructor) with Rust arguments
+    pub fn call(&mut self, name: &str, args: &[LoxObj]) -> Result<LoxObj> {
+        let callee = self
+            .get(name)
+            .ok_or_else(|| RuntimeError::from(RuntimeErrorKind::Undefined(name.to_owned())))?;
+        Ok(self.interpreter.call(&callee, args)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Lox, LoxError};
+    use crate::runtime::{obj::LoxObj, output::SharedBuffer};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn eval_and_read_back() {
+        let mut lox = Lox::new();
+        let n = lox.eval("var x = 1; x + 2;").unwrap();
+        assert_eq!(f64::try_from(n).unwrap(), 3.0);
+
+        lox.set("name", "lox");
+        let s = lox.eval("name + \"rs\";").unwrap();
+        assert_eq!(String::try_from(s).unwrap(), "loxrs");
+
+        let xs = Vec::<LoxObj>::try_from(lox.eval("[1, 2];").unwrap()).unwrap();
+        assert_eq!(xs.len(), 2);
+        assert!(lox.eval("print x;").unwrap().is_nil());
+    }
+
+    #[test]
+    fn call_by_name() {
+        let mut lox = Lox::new();
+        lox.eval("fn add(a, b) { return a + b; }").unwrap();
+        let sum = lox.call("add", &[1.0.into(), 2.0.into()]).unwrap();
+        assert_eq!(f64::try_from(sum).unwrap(), 3.0);
+
+        assert!(lox.call("sub", &[]).is_err());
+        assert!(bool::try_from(lox.get("add").unwrap()).is_err());
+    }
+
+    #[test]
+    fn resolve_each_input() {
+        let mut lox = Lox::new();
+        // the variable uses share the same position
+        let n = lox.eval("fn f(a) {  a; return a; } f(1);").unwrap();
+        assert_eq!(f64::try_from(n).unwrap(), 1.0);
+        let n = lox.eval("var a = 2; a;").unwrap();
+        assert_eq!(f64::try_from(n).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn backtrace() {
+        let mut lox = Lox::new();
+        let src = "class A { fn f() { return g(); } }\nfn g() { return nil + 1; }\nA().f();";
+        let why = match lox.eval(src) {
+            Err(LoxError::Runtime(why)) => why,
+            result => panic!("expected a runtime error: {:?}", result),
+        };
+        let frames = why
+            .backtrace()
+            .iter()
+            .map(|frame| format!("{} {}", frame.name, frame.pos.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, vec!["g 2:21", "A.f 1:28", "script 3:6"]);
+    }
+
+    #[test]
+    fn print_to_buffer() {
+        let out = SharedBuffer::new();
+        let mut lox = Lox::with_output(Box::new(out.clone()));
+        lox.eval("print 1; print \"two\";").unwrap();
+        assert_eq!(out.take(), "1\n\"two\"\n");
+        lox.eval("print [3];").unwrap();
+        assert_eq!(out.contents(), "[3]\n");
+    }
+
+    #[test]
+    fn errors() {
+        let mut lox = Lox::new();
+        assert!(matches!(lox.eval("var x = ;"), Err(LoxError::Parse(_))));
+        assert!(matches!(lox.eval("1 + nil;"), Err(LoxError::Runtime(_))));
+    }
+
+    #[test]
+    fn trailing_expr_as_stmt() {
+        let mut lox = Lox::new();
+        // a step for the statement and one for the expression
+        lox.interpreter().set_fuel(Some(2));
+        lox.eval("1;").unwrap();
+        assert_eq!(lox.interpreter().fuel(), Some(0));
+        lox.interpreter().set_fuel(None);
+
+        // the object thrown out is not received by a later `catch`
+        assert!(lox.eval("fn f() { throw 1; } f();").is_err());
+        lox.eval("var e = nil; try { [][0]; } catch (why) { e = why; }")
+            .unwrap();
+        assert!(matches!(lox.get("e"), Some(LoxObj::Instance(_))));
+    }
+}
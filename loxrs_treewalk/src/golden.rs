@@ -0,0 +1,204 @@
+//! Golden-file tests: runs `.lox` scripts and compares the results with annotations
+//!
+//! In the style of the Crafting Interpreters test suite:
+//!
+//! ```none
+//! print 1 + 2; // expect: 3
+//! print nil + 1; // expect runtime error: mismatched type
+//! ```
+//!
+//! Scripts that fail to compile can be annotated with `// expect error: <message>`, one for each
+//! error reported in order.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    embed::Lox,
+    runtime::{on_script_stack, output::SharedBuffer},
+    LoxError,
+};
+
+const EXPECT_OUTPUT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const EXPECT_ERROR: &str = "// expect error: ";
+
+/// Annotations in a script
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Expectations {
+    /// Printed lines in order
+    pub output: Vec<String>,
+    pub runtime_error: Option<String>,
+    /// Scan, parse or resolve errors in order
+    pub compile_errors: Vec<String>,
+}
+
+impl Expectations {
+    pub fn parse(src: &str) -> Self {
+        let mut expects = Self::default();
+        for line in src.lines() {
+            if let Some(s) = self::annotation(line, EXPECT_OUTPUT) {
+                expects.output.push(s);
+            } else if let Some(s) = self::annotation(line, EXPECT_RUNTIME_ERROR) {
+                expects.runtime_error = Some(s);
+            } else if let Some(s) = self::annotation(line, EXPECT_ERROR) {
+                expects.compile_errors.push(s);
+            }
+        }
+        expects
+    }
+}
+
+fn annotation(line: &str, prefix: &str) -> Option<String> {
+    line.find(prefix)
+        .map(|i| line[i + prefix.len()..].trim_end().to_string())
+}
+
+/// Runs a script and returns mismatches with the expectations (empty if it passed). It runs on a
+/// large stack as the CLI does.
+pub fn check(src: &str) -> Vec<String> {
+    on_script_stack(|| self::check_on_current_thread(src))
+}
+
+fn check_on_current_thread(src: &str) -> Vec<String> {
+    let expects = Expectations::parse(src);
+    let out = SharedBuffer::new();
+    let mut lox = Lox::with_output(Box::new(out.clone()));
+    let result = lox.eval(src);
+
+    let mut failures = Vec::new();
+    let output = out.contents();
+    let output = output.lines().map(|l| l.to_string()).collect::<Vec<_>>();
+    self::check_lines(&mut failures, "output", &expects.output, &output);
+
+    let (runtime_error, compile_errors) = match result {
+        Ok(_) => (None, Vec::new()),
+        Err(LoxError::Runtime(why)) => (Some(why.to_string()), Vec::new()),
+        Err(LoxError::Scan(errors)) => (None, errors.iter().map(|e| e.to_string()).collect()),
+        Err(LoxError::Parse(errors)) => (None, errors.iter().map(|e| e.to_string()).collect()),
+        Err(LoxError::Resolve(why)) => (None, vec![why.to_string()]),
+    };
+    self::check_error(
+        &mut failures,
+        "runtime error",
+        &expects.runtime_error,
+        &runtime_error,
+    );
+    self::check_lines(
+        &mut failures,
+        "error",
+        &expects.compile_errors,
+        &compile_errors,
+    );
+
+    failures
+}
+
+/// Compares expected and found items in order
+fn check_lines(failures: &mut Vec<String>, name: &str, expected: &[String], found: &[String]) {
+    for i in 0..found.len().max(expected.len()) {
+        match (expected.get(i), found.get(i)) {
+            (Some(expected), Some(found)) if expected == found => {}
+            (Some(expected), Some(found)) => failures.push(format!(
+                "expected {} `{}`, found `{}`",
+                name, expected, found
+            )),
+            (Some(expected), None) => {
+                failures.push(format!("expected {} `{}`, found nothing", name, expected))
+            }
+            (None, Some(found)) => failures.push(format!("unexpected {} `{}`", name, found)),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+fn check_error(
+    failures: &mut Vec<String>,
+    name: &str,
+    expected: &Option<String>,
+    found: &Option<String>,
+) {
+    match (expected, found) {
+        (Some(expected), Some(found)) if expected == found => {}
+        (Some(expected), Some(found)) => failures.push(format!(
+            "expected {} `{}`, found `{}`",
+            name, expected, found
+        )),
+        (Some(expected), None) => {
+            failures.push(format!("expected {} `{}`, found nothing", name, expected))
+        }
+        (None, Some(found)) => failures.push(format!("unexpected {} `{}`", name, found)),
+        (None, None) => {}
+    }
+}
+
+/// Results of running scripts in a directory
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, Vec<String>)>,
+}
+
+/// Checks all the `.lox` files in a directory recursively, in alphabetical order
+pub fn check_dir(dir: &Path) -> io::Result<Report> {
+    let mut report = Report::default();
+    for path in self::lox_files(dir)? {
+        let src = fs::read_to_string(&path)?;
+        let failures = self::check(&src);
+        if failures.is_empty() {
+            report.passed.push(path);
+        } else {
+            report.failed.push((path, failures));
+        }
+    }
+    Ok(report)
+}
+
+fn lox_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(self::lox_files(&path)?);
+        } else if path.extension() == Some("lox".as_ref()) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, Expectations};
+
+    #[test]
+    fn parse_expectations() {
+        let src = "print 1; // expect: 1\nprint x; // expect runtime error: oops\n";
+        let expects = Expectations::parse(src);
+        assert_eq!(expects.output, vec!["1".to_string()]);
+        assert_eq!(expects.runtime_error, Some("oops".to_string()));
+        assert!(expects.compile_errors.is_empty());
+    }
+
+    #[test]
+    fn mismatches() {
+        assert!(check("print 1; // expect: 1").is_empty());
+        assert_eq!(check("print 1; // expect: 2").len(), 1);
+        assert_eq!(check("print 1;").len(), 1);
+        assert!(check("print nil + 1; // expect runtime error: mismatched type").is_empty());
+        assert_eq!(check("print nil + 1;").len(), 1);
+    }
+
+    #[test]
+    fn multiple_compile_errors() {
+        let err = "// expect error: expected one of number, string, `false`, `true`, `nil`, `(`, `[`, `{`, found `;`";
+        let both = format!("print ; {}\nprint ; {}\n", err, err);
+        assert!(check(&both).is_empty(), "{:?}", check(&both));
+        // the second error is not ignored
+        let first = format!("print ; {}\nprint ;\n", err);
+        assert_eq!(check(&first).len(), 1);
+    }
+}
@@ -12,11 +12,16 @@ use std::rc::Rc;
 // Errors
 
 type Result<T> = std::result::Result<T, ParseError>;
+use thiserror::Error;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Error)]
 pub enum ParseError {
-    UnexpectedEof,
+    /// Located at the last token
+    #[error("unexpected end of file")]
+    UnexpectedEof(Location),
+    #[error("{0}")]
     UnexpectedToken(UnexpectedTokenErrorArgs),
+    #[error("not assignable expression")]
     NotAssignable(Expr),
 }
 
@@ -25,8 +30,16 @@ impl ParseError {
         ParseError::UnexpectedToken(UnexpectedTokenErrorArgs::from_token(found, expected))
     }
 
-    pub fn eof() -> Self {
-        ParseError::UnexpectedEof
+    pub fn eof(pos: Location) -> Self {
+        ParseError::UnexpectedEof(pos)
+    }
+
+    pub fn pos(&self) -> Location {
+        match self {
+            ParseError::UnexpectedEof(pos) => *pos,
+            ParseError::UnexpectedToken(args) => args.pos,
+            ParseError::NotAssignable(expr) => expr.pos(),
+        }
     }
 }
 
@@ -37,6 +50,23 @@ pub struct UnexpectedTokenErrorArgs {
     found: TokenKind,
 }
 
+/// expected `;`, found `}`
+impl std::fmt::Display for UnexpectedTokenErrorArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let expected = self
+            .expected
+            .iter()
+            .map(|tk| tk.describe())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if self.expected.len() == 1 {
+            write!(f, "expected {}, found {}", expected, self.found)
+        } else {
+            write!(f, "expected one of {}, found {}", expected, self.found)
+        }
+    }
+}
+
 impl UnexpectedTokenErrorArgs {
     // TODO: more generic interface
     pub fn from_token(tk: &Token, expected: &[TokenKind]) -> Self {
@@ -56,7 +86,8 @@ where
     I: Iterator<Item = &'a Token> + Sized,
 {
     tks: Peekable<I>,
-    counter: VarUseIdCounter,
+    /// Position of the last consumed token (for reporting unexpected EoF)
+    prev_pos: Location,
 }
 
 impl<'a> Parser<'a, std::slice::Iter<'a, Token>> {
@@ -64,7 +95,7 @@ impl<'a> Parser<'a, std::slice::Iter<'a, Token>> {
     pub fn new(tokens: &'a [Token]) -> Self {
         Parser {
             tks: tokens.iter().peekable(),
-            counter: VarUseIdCounter::new(),
+            prev_pos: Location::initial(),
         }
     }
 }
@@ -78,22 +109,28 @@ where
         self.tks.peek()
     }
 
-    fn next(&mut self) -> Option<&Token> {
-        self.tks.next()
+    fn next(&mut self) -> Option<&'a Token> {
+        let next = self.tks.next();
+        if let Some(tk) = next {
+            self.prev_pos = tk.pos;
+        }
+        next
     }
 
     fn advance(&mut self) -> bool {
-        self.tks.next().is_some()
+        self.next().is_some()
     }
 
     /// Peek or else error
     fn try_peek(&mut self) -> Result<&&Token> {
-        self.peek().ok_or(ParseError::eof())
+        let pos = self.prev_pos;
+        self.peek().ok_or(ParseError::eof(pos))
     }
 
     /// Next or else error
-    fn try_next(&mut self) -> Result<&Token> {
-        self.next().ok_or(ParseError::eof())
+    fn try_next(&mut self) -> Result<&'a Token> {
+        let pos = self.prev_pos;
+        self.next().ok_or(ParseError::eof(pos))
     }
 
     /// Just a wrapper around `Iterator::find`.
@@ -102,7 +139,7 @@ where
     }
 
     /// Safely tries to advance the token iterator
-    fn consume(&mut self, expected: &TokenKind) -> Option<&Token> {
+    fn consume(&mut self, expected: &TokenKind) -> Option<&'a Token> {
         match self.peek() {
             Some(tk) if tk.kind == *expected => Some(self.next().unwrap()),
             _ => None,
@@ -110,35 +147,32 @@ where
     }
 
     /// Tries to consume the expected token or cause an error
-    fn try_consume(&mut self, expected: &TokenKind) -> Result<&Token> {
+    fn try_consume(&mut self, expected: &TokenKind) -> Result<&'a Token> {
+        let pos = self.prev_pos;
         match self.peek() {
             Some(tk) if tk.kind == *expected => Ok(self.next().unwrap()),
             Some(tk) => Err(ParseError::unexpected(tk, &[expected.clone()])),
-            None => Err(ParseError::eof()),
+            None => Err(ParseError::eof(pos)),
         }
     }
 
-    fn try_consume_identifier(&mut self) -> Result<String> {
-        if let Some(tk) = self.peek() {
-            if let TokenKind::Ident(ref name) = tk.kind {
-                let name = name.clone();
-                self.advance();
-                Ok(name)
-            } else {
-                Err(ParseError::unexpected(tk, &[TokenKind::Ident("".into())]))
-            }
+    /// Returns the name and the position of the identifier
+    fn try_consume_identifier(&mut self) -> Result<(String, Location)> {
+        let tk = self.try_next()?;
+        if let TokenKind::Ident(ref name) = tk.kind {
+            Ok((name.clone(), tk.pos))
         } else {
-            Err(ParseError::eof())
+            Err(ParseError::unexpected(tk, &[TokenKind::Ident("".into())]))
         }
     }
 
     // cannot identify token with fields
-    fn consume_one_of(&mut self, expected: &[TokenKind]) -> Option<TokenKind> {
-        let opt = Self::_find(self.peek()?, expected);
-        if opt.is_some() {
-            self.next();
+    fn consume_one_of(&mut self, expected: &[TokenKind]) -> Option<&'a Token> {
+        if Self::_find(self.peek()?, expected).is_some() {
+            self.next()
+        } else {
+            None
         }
-        opt
     }
 
     /// Just a wrapper of `Iterator::any`. Fails if nothing is found.
@@ -183,9 +217,8 @@ where
                     break;
                 }
                 _ => {
-                    let stmt = self
-                        .decl()
-                        .unwrap_or_else(|| Err(ParseError::UnexpectedEof))?;
+                    let pos = self.prev_pos;
+                    let stmt = self.decl().unwrap_or_else(|| Err(ParseError::eof(pos)))?;
                     stmts.push(stmt);
                 }
             };
@@ -229,9 +262,16 @@ where
         })
     }
 
-    /// declClass  → "class" IDENTIFIER "{" function* "}" ;
+    /// declClass  → "class" IDENTIFIER ( "<" IDENTIFIER )? "{" function* "}" ;
     fn decl_class(&mut self) -> Result<ClassDeclArgs> {
-        let name = self.try_consume_identifier()?;
+        let (name, pos) = self.try_consume_identifier()?;
+        let superclass = match self.consume(&TokenKind::Less) {
+            Some(_) => {
+                let (name, pos) = self.try_consume_identifier()?;
+                Some(VarUseData::new(&name, pos))
+            }
+            None => None,
+        };
         self.try_consume(&TokenKind::LeftBrace)?;
         let mut methods = Vec::new();
         while self.consume(&TokenKind::Fn).is_some() {
@@ -239,12 +279,12 @@ where
             methods.push(method);
         }
         self.try_consume(&TokenKind::RightBrace)?;
-        Ok(ClassDeclArgs::new(name, methods))
+        Ok(ClassDeclArgs::new(name, superclass, methods, pos))
     }
 
     /// declFn  → "fn" IDENTIFIER "(" params? ")" block ;
     fn decl_fn(&mut self) -> Result<FnDeclArgs> {
-        let name = self.try_consume_identifier()?;
+        let (name, pos) = self.try_consume_identifier()?;
 
         self.try_consume(&TokenKind::LeftParen)?;
         let params = match self.try_peek()?.kind {
@@ -257,19 +297,19 @@ where
         self.try_consume(&TokenKind::LeftBrace)?;
         let body = self.parse_block()?;
 
-        Ok(FnDeclArgs::new(name, Rc::new(body), params))
+        Ok(FnDeclArgs::new(name, Rc::new(body), params, pos))
     }
 
     /// params → IDENTIFIER ( "," IDENTIFIER )* ;
     fn params(&mut self) -> Result<Params> {
         let mut params = Vec::new();
-        params.push(self.try_consume_identifier()?);
+        params.push(self.try_consume_identifier()?.0);
         while match self.peek() {
             Some(tk) if tk.kind == TokenKind::Comma => true,
             _ => false,
         } {
             self.advance();
-            params.push(self.try_consume_identifier()?);
+            params.push(self.try_consume_identifier()?.0);
         }
         Ok(params)
     }
@@ -279,39 +319,72 @@ where
     /// It always requires initializer, different from the original Lox.
     /// Call it after consuming `var`.
     fn decl_var(&mut self) -> Result<Stmt> {
-        let name = self.try_consume_identifier()?;
+        let (name, pos) = self.try_consume_identifier()?;
         self.try_consume(&TokenKind::Eq)?;
         let init = self.expr()?;
         self.try_consume(&TokenKind::Semicolon)?;
-        Ok(Stmt::var_dec(name, init))
+        Ok(Stmt::var_dec(name, init, pos))
     }
 
-    /// stmt → exprStmt | printStmt | returnStmt whileStmt | block ;
+    /// stmt → exprStmt | printStmt | returnStmt whileStmt | forStmt | block | breakStmt
+    ///      | continueStmt | throwStmt | tryStmt ;
+    ///
+    /// breakStmt    → "break" ";" ;
+    /// continueStmt → "continue" ";" ;
+    /// throwStmt    → "throw" expression ";" ;
     ///
     /// The root of predictive statement parsing. Sub rules are named as `stmt_xxx`.
     /// Note that sub rules don't consume unexpected tokens.
     pub fn stmt(&mut self) -> Result<Stmt> {
         use TokenKind::*;
+        let pos = self.try_peek()?.pos;
         match &self.try_peek()?.kind {
             Print => {
                 self.next();
-                self.stmt_print()
+                self.stmt_print(pos)
             }
             LeftBrace => {
                 self.next();
-                Ok(self.stmt_block()?.into_stmt())
+                Ok(self.stmt_block(pos)?.into_stmt())
             }
             If => {
                 self.next();
-                self.stmt_if()
+                self.stmt_if(pos)
             }
             Return => {
                 self.next();
-                self.stmt_return()
+                self.stmt_return(pos)
             }
             While => {
                 self.next();
-                self.stmt_while()
+                self.stmt_while(pos)
+            }
+            For => {
+                self.next();
+                self.stmt_for(pos)
+            }
+            Break => {
+                self.next();
+                self.try_consume(&Semicolon)?;
+                Ok(Stmt::Break(pos))
+            }
+            Continue => {
+                self.next();
+                self.try_consume(&Semicolon)?;
+                Ok(Stmt::Continue(pos))
+            }
+            Throw => {
+                self.next();
+                let expr = self.expr()?;
+                self.try_consume(&Semicolon)?;
+                Ok(Stmt::Throw(ThrowArgs {
+                    expr: expr,
+                    pos: pos,
+                }))
+            }
+            Try => {
+                self.next();
+                self.stmt_try(pos)
             }
             _ => self.stmt_expr(),
         }
@@ -320,35 +393,36 @@ where
     /// printStmt → "print" expression ";" ;
     ///
     /// To be called after consuming `print` (predictive parsing).
-    fn stmt_print(&mut self) -> Result<Stmt> {
+    fn stmt_print(&mut self, pos: Location) -> Result<Stmt> {
         let expr = self.expr()?;
         self.try_consume(&TokenKind::Semicolon)?;
         // TODO: adding Expr -> String functions for printing
-        Ok(Stmt::print(expr))
+        Ok(Stmt::print(expr, pos))
     }
 
     /// block → "{" declaration* "}" ;
     ///
-    /// Left brace `{` must be consumed before calling this.
-    pub fn stmt_block(&mut self) -> Result<BlockArgs> {
+    /// Left brace `{` must be consumed before calling this. `pos` is the position of it.
+    pub fn stmt_block(&mut self, pos: Location) -> Result<BlockArgs> {
         Ok(BlockArgs {
             stmts: self.parse_block()?,
+            pos: pos,
         })
     }
 
     /// if → "if" expr block elseRecursive
-    pub fn stmt_if(&mut self) -> Result<Stmt> {
+    pub fn stmt_if(&mut self, pos: Location) -> Result<Stmt> {
         // TODO: no overhead
-        let if_ = self.parse_if()?;
+        let if_ = self.parse_if(pos)?;
         Ok(Stmt::If(Box::new(if_)))
     }
 
-    fn parse_if(&mut self) -> Result<IfArgs> {
+    fn parse_if(&mut self, pos: Location) -> Result<IfArgs> {
         let condition = self.expr()?;
-        self.try_consume(&TokenKind::LeftBrace)?;
-        let if_true = self.stmt_block()?;
+        let block_pos = self.try_consume(&TokenKind::LeftBrace)?.pos;
+        let if_true = self.stmt_block(block_pos)?;
         let if_false = self._else_recursive()?;
-        Ok(IfArgs::new(condition, if_true, if_false))
+        Ok(IfArgs::new(condition, if_true, if_false, pos))
     }
 
     /// elseRecursive → ("else" "if" block)* ("else" block)?
@@ -357,19 +431,19 @@ where
             return Ok(None);
         }
         let tk = self.try_peek()?;
+        let pos = tk.pos;
         match tk.kind {
             // else if
             TokenKind::If => {
                 self.advance();
-                let else_if = self.parse_if()?;
+                let else_if = self.parse_if(pos)?;
                 Ok(Some(ElseBranch::else_if(else_if)))
             }
             // else
             TokenKind::LeftBrace => {
                 self.advance();
-                let else_ = self.parse_block()?;
-                // TODO: no overhead with new type pattern
-                Ok(Some(ElseBranch::JustElse(BlockArgs { stmts: else_ })))
+                let else_ = self.stmt_block(pos)?;
+                Ok(Some(ElseBranch::JustElse(else_)))
             }
             // error
             _ => Err(ParseError::unexpected(
@@ -380,18 +454,74 @@ where
     }
 
     /// stmtReturn → "return" expression? ";" ;
-    pub fn stmt_return(&mut self) -> Result<Stmt> {
+    pub fn stmt_return(&mut self, pos: Location) -> Result<Stmt> {
         let expr = self.expr()?;
         self.try_consume(&TokenKind::Semicolon)?;
-        Ok(Stmt::return_(expr))
+        Ok(Stmt::return_(expr, pos))
     }
 
     /// while → "while" expr block
-    pub fn stmt_while(&mut self) -> Result<Stmt> {
+    pub fn stmt_while(&mut self, pos: Location) -> Result<Stmt> {
         let condition = self.expr()?;
-        self.try_consume(&TokenKind::LeftBrace)?;
-        let block = self.stmt_block()?;
-        Ok(Stmt::while_(condition, block))
+        let block_pos = self.try_consume(&TokenKind::LeftBrace)?.pos;
+        let block = self.stmt_block(block_pos)?;
+        Ok(Stmt::while_(condition, block, pos))
+    }
+
+    /// for → "for" IDENTIFIER "in" expr ( ".." expr )? block
+    pub fn stmt_for(&mut self, pos: Location) -> Result<Stmt> {
+        let (var, _) = self.try_consume_identifier()?;
+        self.try_consume(&TokenKind::In)?;
+        let iter = self.expr()?;
+        let iter = match self.consume(&TokenKind::DotDot) {
+            Some(_) => ForIter::Range(iter, self.expr()?),
+            None => ForIter::Each(iter),
+        };
+        let block_pos = self.try_consume(&TokenKind::LeftBrace)?.pos;
+        let block = self.stmt_block(block_pos)?;
+        Ok(Stmt::for_(var, iter, block, pos))
+    }
+
+    /// try → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )?
+    ///
+    /// At least one of `catch` and `finally` is required.
+    pub fn stmt_try(&mut self, pos: Location) -> Result<Stmt> {
+        let block_pos = self.try_consume(&TokenKind::LeftBrace)?.pos;
+        let body = self.stmt_block(block_pos)?;
+        let catch = match self.consume(&TokenKind::Catch) {
+            Some(_) => {
+                self.try_consume(&TokenKind::LeftParen)?;
+                let (var, _) = self.try_consume_identifier()?;
+                self.try_consume(&TokenKind::RightParen)?;
+                let block_pos = self.try_consume(&TokenKind::LeftBrace)?.pos;
+                let block = self.stmt_block(block_pos)?;
+                Some(CatchArgs {
+                    var: var,
+                    block: block,
+                })
+            }
+            None => None,
+        };
+        let finally = match self.consume(&TokenKind::Finally) {
+            Some(_) => {
+                let block_pos = self.try_consume(&TokenKind::LeftBrace)?.pos;
+                Some(self.stmt_block(block_pos)?)
+            }
+            None => None,
+        };
+        if catch.is_none() && finally.is_none() {
+            let tk = self.try_peek()?;
+            return Err(ParseError::unexpected(
+                tk,
+                &[TokenKind::Catch, TokenKind::Finally],
+            ));
+        }
+        Ok(Stmt::Try(Box::new(TryArgs {
+            body: body,
+            catch: catch,
+            finally: finally,
+            pos: pos,
+        })))
     }
 
     /// Expression statement or (recursive) assignment
@@ -424,13 +554,13 @@ where
     where
         TokenKind: Into<Option<Oper>>,
         SubRule: Fn(&mut Self) -> Result<Expr>,
-        Folder: Fn(Expr, Oper, Expr) -> Expr,
+        Folder: Fn(Expr, Oper, Expr, Location) -> Expr,
     {
         let mut expr = sub_rule(self)?;
         while let Some(token) = self.consume_one_of(delimiters) {
             let right = sub_rule(self)?;
-            let oper = token.into().unwrap();
-            expr = folder(expr, oper, right);
+            let oper = token.kind.clone().into().unwrap();
+            expr = folder(expr, oper, right, token.pos);
         }
         Ok(expr)
     }
@@ -440,28 +570,36 @@ where
         self.expr_assign()
     }
 
-    /// assignment → ( call "." )? IDENTIFIER "=" assignment
+    /// assignment → ( call "." )? IDENTIFIER ( "=" | "+=" | "-=" | "*=" | "/=" ) assignment
     ///            | logic_or;
     fn expr_assign(&mut self) -> Result<Expr> {
         let lhs = self.expr_or()?;
 
         // peek to see if it's an assignment
-        if self.consume(&TokenKind::Eq).is_none() {
-            return Ok(lhs);
+        use TokenKind::*;
+        let (oper, pos) = match self.consume_one_of(&[Eq, PlusEq, MinusEq, StarEq, SlashEq]) {
+            Some(tk) => (Option::<AssignOper>::from(tk.kind.clone()).unwrap(), tk.pos),
+            None => return Ok(lhs),
         };
 
         match lhs {
             // assign
             Expr::Variable(ref var) => {
                 let rhs = self.expr_assign()?;
-                return Ok(Expr::assign(&var.name, rhs, self.counter.next()));
+                let assigned = VarUseData::new(&var.name, var.pos);
+                return Ok(Expr::assign(assigned, oper, rhs, pos));
             }
             // set (assign to get expression)
             Expr::Get(get) => {
                 // e.g. x.y.z = 3;  // x, y are Expr::Get, z is Expr::Set
                 let name = get.name.clone();
                 let rhs = self.expr_assign()?;
-                return Ok(Expr::set(get.body, &name, rhs));
+                return Ok(Expr::set(get.body, &name, oper, rhs, get.pos));
+            }
+            // set an item (assign to index expression)
+            Expr::Index(index) => {
+                let rhs = self.expr_assign()?;
+                return Ok(Expr::set_index(*index, oper, rhs, pos));
             }
             // error
             _ => {
@@ -511,43 +649,47 @@ where
     /// unary → ( "!" | "-" ) unary | call ;
     fn expr_unary(&mut self) -> Result<Expr> {
         use TokenKind::*;
+        let pos = self.try_peek()?.pos;
         match self.try_peek()?.kind {
             Bang => {
                 self.advance();
-                Ok(Expr::unary(UnaryOper::Not, self.expr_unary()?))
+                Ok(Expr::unary(UnaryOper::Not, self.expr_unary()?, pos))
             }
             Minus => {
                 self.advance();
-                Ok(Expr::unary(UnaryOper::Minus, self.expr_unary()?))
+                Ok(Expr::unary(UnaryOper::Minus, self.expr_unary()?, pos))
             }
             _ => self.expr_call(),
         }
     }
 
-    /// call → primary (invoke|prop)* ;
+    /// call → primary (invoke|prop|index)* ;
     fn expr_call(&mut self) -> Result<Expr> {
         let mut expr = self.expr_prim()?;
 
         // TODO: use right recursive parsing
         loop {
+            let pos = self.try_peek()?.pos;
             match self.try_peek()?.kind {
                 TokenKind::LeftParen => {
-                    // invoke → "(" args ")"
+                    // invoke → "(" args? ")"
                     self.advance();
-                    let args = if self.try_peek()?.kind == TokenKind::RightParen {
-                        self.advance();
-                        Vec::new()
-                    } else {
-                        let args = self.expr_call_args()?;
-                        args
-                    };
-                    expr = Expr::call(expr, args);
+                    let args = self.expr_args(&TokenKind::RightParen)?;
+                    expr = Expr::call(expr, args, pos);
+                }
+
+                TokenKind::LeftBracket => {
+                    // index → "[" expr "]"
+                    self.advance();
+                    let index = self.expr()?;
+                    self.try_consume(&TokenKind::RightBracket)?;
+                    expr = Expr::index(expr, index, pos);
                 }
 
                 TokenKind::Dot => {
                     self.advance();
-                    let name = self.try_consume_identifier()?;
-                    expr = Expr::get(expr, &name);
+                    let (name, pos) = self.try_consume_identifier()?;
+                    expr = Expr::get(expr, &name, pos);
                 }
 
                 _ => {
@@ -558,9 +700,14 @@ where
     }
 
     /// args → expr ( "," expr )* ;
+    ///
+    /// To be called after consuming `(` or `[`. Consumes the closing token, too.
     // TODO: use rrp
-    fn expr_call_args(&mut self) -> Result<Args> {
+    fn expr_args(&mut self, close: &TokenKind) -> Result<Args> {
         let mut args = Args::new();
+        if self.consume(close).is_some() {
+            return Ok(args);
+        }
         args.push(self.expr()?);
         loop {
             match self.try_peek()? {
@@ -568,49 +715,118 @@ where
                     self.advance();
                     args.push(self.expr()?);
                 }
-                tk if tk.kind == TokenKind::RightParen => {
+                tk if tk.kind == *close => {
                     self.advance();
                     return Ok(args);
                 }
                 tk => {
                     return Err(ParseError::unexpected(
                         tk,
-                        &[TokenKind::Comma, TokenKind::RightParen],
+                        &[TokenKind::Comma, close.clone()],
                     ));
                 }
             }
         }
     }
 
-    /// primary → literal | group | indentifier | self ;
+    /// entries → expr ":" expr ( "," expr ":" expr )* ;
+    ///
+    /// To be called after consuming `{`. Consumes the closing `}`, too.
+    fn expr_entries(&mut self) -> Result<Vec<(Expr, Expr)>> {
+        let mut entries = Vec::new();
+        if self.consume(&TokenKind::RightBrace).is_some() {
+            return Ok(entries);
+        }
+        entries.push(self.expr_entry()?);
+        loop {
+            match self.try_peek()? {
+                tk if tk.kind == TokenKind::Comma => {
+                    self.advance();
+                    entries.push(self.expr_entry()?);
+                }
+                tk if tk.kind == TokenKind::RightBrace => {
+                    self.advance();
+                    return Ok(entries);
+                }
+                tk => {
+                    return Err(ParseError::unexpected(
+                        tk,
+                        &[TokenKind::Comma, TokenKind::RightBrace],
+                    ));
+                }
+            }
+        }
+    }
+
+    fn expr_entry(&mut self) -> Result<(Expr, Expr)> {
+        let key = self.expr()?;
+        self.try_consume(&TokenKind::Colon)?;
+        let value = self.expr()?;
+        Ok((key, value))
+    }
+
+    /// primary → literal | group | list | map | indentifier | self | super ;
     ///
     /// literal → number | string | "false" | "true" | "nil" ;
     /// group   → "(" expression ")" ;
+    /// list    → "[" args? "]" ;
+    /// map     → "{" entries? "}" ;
+    /// super   → "super" "." IDENTIFIER ;
     ///
     /// Make sure that there exists next token (predictive parsing).
     fn expr_prim(&mut self) -> Result<Expr> {
         // TODO: refactor
-        let mut var = {
+        let var = {
             let tk = self.try_next()?;
             use TokenKind::*;
             let name = match tk.kind {
                 Ident(ref name) => name,
                 LeftParen => return self.expr_group(),
-                Self_ => return Ok(Expr::Self_(SelfData {})),
+                LeftBracket => {
+                    let items = self.expr_args(&RightBracket)?;
+                    return Ok(Expr::list(items, tk.pos));
+                }
+                LeftBrace => {
+                    let entries = self.expr_entries()?;
+                    return Ok(Expr::map(entries, tk.pos));
+                }
+                Self_ => {
+                    return Ok(Expr::Self_(SelfData {
+                        var: VarUseData::new("@", tk.pos),
+                        pos: tk.pos,
+                    }))
+                }
+                Super => {
+                    self.try_consume(&Dot)?;
+                    let (method, _) = self.try_consume_identifier()?;
+                    return Ok(Expr::Super(SuperData {
+                        method: method,
+                        var: VarUseData::new("super", tk.pos),
+                        pos: tk.pos,
+                    }));
+                }
                 _ => {
-                    if let Some(literal) = LiteralData::from_token(&tk.kind) {
-                        return Ok(literal.into());
+                    if let Some(value) = LiteralValue::from_token(&tk.kind) {
+                        return Ok(Expr::literal(value, tk.pos));
                     }
                     return Err(ParseError::unexpected(
                         tk,
                         // TODO: abstract token for literals
-                        &[Num(0.0), Str("".into()), False, True, Nil, LeftParen],
+                        &[
+                            Num(0.0),
+                            Str("".into()),
+                            False,
+                            True,
+                            Nil,
+                            LeftParen,
+                            LeftBracket,
+                            LeftBrace,
+                        ],
                     ));
                 }
             };
-            VarUseData::new(name, VarUseId::new())
+            VarUseData::new(name, tk.pos)
         };
-        var.id = self.counter.next();
         Ok(Expr::Variable(var))
     }
 
@@ -635,7 +851,7 @@ impl SyncPeekChecker {
     pub fn check_token<T: Borrow<TokenKind>>(token: T) -> Self {
         use TokenKind::*;
         match token.borrow() {
-            Class | Fn | Var | If | For | While | Print | Return => Self {
+            Class | Fn | Var | If | For | While | Print | Return | Throw | Try => Self {
                 needs_advance: false,
                 ends: true,
             },
@@ -650,3 +866,169 @@ impl SyncPeekChecker {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::ast::{expr::Expr, stmt::Stmt};
+    use crate::lexer::{parser::Parser, scanner::Scanner, token::Location};
+
+    /// Each node is located at the token that represents it, skipping whitespaces and comments
+    #[test]
+    fn node_positions() {
+        let src = "var x = 1;\n/* comment */ print  x +\n    2;";
+        let (tks, scan_errors) = Scanner::new(src).scan();
+        assert!(scan_errors.is_empty());
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        assert!(parse_errors.is_empty());
+
+        assert_eq!(stmts[0].pos(), Location::new(1, 5));
+        assert_eq!(stmts[1].pos(), Location::new(2, 15));
+        match stmts[1] {
+            Stmt::Print(ref print) => match print.expr {
+                Expr::Binary(ref binary) => {
+                    assert_eq!(binary.pos, Location::new(2, 24));
+                    assert_eq!(binary.left.pos(), Location::new(2, 22));
+                    assert_eq!(binary.right.pos(), Location::new(3, 5));
+                }
+                ref expr => panic!("expected binary expression: {:?}", expr),
+            },
+            ref stmt => panic!("expected print statement: {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn superclass_and_super() {
+        let src = "class B < A { fn f() { return super.f; } }";
+        let (tks, _) = Scanner::new(src).scan();
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        assert!(parse_errors.is_empty());
+
+        let class = match stmts[0] {
+            Stmt::Class(ref class) => class,
+            ref stmt => panic!("expected class declaration: {:?}", stmt),
+        };
+        let superclass = class.superclass.as_ref().unwrap();
+        assert_eq!(superclass.name, "A");
+        assert_eq!(superclass.pos, Location::new(1, 11));
+        match class.methods[0].body[0] {
+            Stmt::Return(ref ret) => match ret.expr {
+                Expr::Super(ref super_) => assert_eq!(super_.method, "f"),
+                ref expr => panic!("expected super expression: {:?}", expr),
+            },
+            ref stmt => panic!("expected return statement: {:?}", stmt),
+        }
+    }
+
+    /// `1..n` is not scanned as a number with a decimal point
+    #[test]
+    fn for_range() {
+        use crate::ast::stmt::ForIter;
+        let (tks, _) = Scanner::new("for i in 1..n { print i; }").scan();
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        assert!(parse_errors.is_empty());
+
+        match stmts[0] {
+            Stmt::For(ref for_) => {
+                assert_eq!(for_.var, "i");
+                match for_.iter {
+                    ForIter::Range(ref start, ref end) => {
+                        assert_eq!(start.pos(), Location::new(1, 10));
+                        assert_eq!(end.pos(), Location::new(1, 13));
+                    }
+                    ref iter => panic!("expected range: {:?}", iter),
+                }
+            }
+            ref stmt => panic!("expected for statement: {:?}", stmt),
+        }
+    }
+
+    /// `a.b.c += 1` sets `c` on `a.b` and `x / y` is a division
+    #[test]
+    fn compound_assignment() {
+        use crate::ast::expr::{AssignOper, BinaryOper};
+        let (tks, _) = Scanner::new("a.b.c += 1; x /= y / 2;").scan();
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        assert!(parse_errors.is_empty());
+
+        match stmts[0] {
+            Stmt::Expr(Expr::Set(ref set)) => {
+                assert_eq!(set.name, "c");
+                assert_eq!(set.oper, AssignOper::Plus);
+                match set.body {
+                    Expr::Get(ref get) => assert_eq!(get.name, "b"),
+                    ref expr => panic!("expected get expression: {:?}", expr),
+                }
+            }
+            ref stmt => panic!("expected set expression: {:?}", stmt),
+        }
+        match stmts[1] {
+            Stmt::Expr(Expr::Assign(ref assign)) => {
+                assert_eq!(assign.oper, AssignOper::Div);
+                match assign.expr {
+                    Expr::Binary(ref binary) => assert_eq!(binary.oper, BinaryOper::Div),
+                    ref expr => panic!("expected binary expression: {:?}", expr),
+                }
+            }
+            ref stmt => panic!("expected assign expression: {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn list_and_index() {
+        let (tks, _) = Scanner::new("xs[0] = [1, 2];").scan();
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        assert!(parse_errors.is_empty());
+
+        match stmts[0] {
+            Stmt::Expr(Expr::SetIndex(ref set)) => {
+                assert_eq!(set.index.pos(), Location::new(1, 4));
+                match set.value {
+                    Expr::List(ref list) => assert_eq!(list.items.len(), 2),
+                    ref expr => panic!("expected list expression: {:?}", expr),
+                }
+            }
+            ref stmt => panic!("expected set index expression: {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn map_literal() {
+        let (tks, _) = Scanner::new("var m = {\"a\": 1, 2: [3]};").scan();
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        assert!(parse_errors.is_empty());
+
+        match stmts[0] {
+            Stmt::Var(ref var) => match var.init {
+                Expr::Map(ref map) => {
+                    assert_eq!(map.pos, Location::new(1, 9));
+                    assert_eq!(map.entries.len(), 2);
+                    assert_eq!(map.entries[1].1.pos(), Location::new(1, 21));
+                }
+                ref expr => panic!("expected map expression: {:?}", expr),
+            },
+            ref stmt => panic!("expected variable declaration: {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn try_catch_finally() {
+        let (tks, _) = Scanner::new("try { throw 1; } catch (e) { print e; } finally {}").scan();
+        let (stmts, parse_errors) = Parser::new(&tks).parse();
+        assert!(parse_errors.is_empty());
+
+        match stmts[0] {
+            Stmt::Try(ref try_) => {
+                assert_eq!(try_.pos, Location::new(1, 1));
+                assert_eq!(try_.body.stmts[0].pos(), Location::new(1, 7));
+                assert_eq!(try_.catch.as_ref().unwrap().var, "e");
+                assert!(try_.finally.is_some());
+            }
+            ref stmt => panic!("expected try statement: {:?}", stmt),
+        }
+
+        // either `catch` or `finally` is required
+        let (tks, _) = Scanner::new("try {} print 1;").scan();
+        let (_, parse_errors) = Parser::new(&tks).parse();
+        assert_eq!(parse_errors[0].pos(), Location::new(1, 8));
+    }
+}
@@ -143,16 +143,35 @@ mod char_ext {
 }
 
 type Result<T> = std::result::Result<T, ScanError>;
-#[derive(Debug, Clone)]
+use thiserror::Error;
+
+#[derive(Debug, Clone, Error)]
 pub enum ScanError {
+    #[error("unterminated string")]
     UnterminatedString(Location),
+    #[error("unterminated range comment")]
     UnterminatedRangeComment(Location),
+    #[error("unexpected end of file")]
     UnexpectedEof(Location),
+    #[error("unexpected character: '{0}'")]
     UnexpectedCharacter(char, Location),
 }
 
+impl ScanError {
+    pub fn pos(&self) -> Location {
+        match self {
+            ScanError::UnterminatedString(pos)
+            | ScanError::UnterminatedRangeComment(pos)
+            | ScanError::UnexpectedEof(pos)
+            | ScanError::UnexpectedCharacter(_, pos) => *pos,
+        }
+    }
+}
+
 pub struct Scanner<'a> {
     chars: self::hidden::CharReader<Chars<'a>>,
+    /// Where the current token starts (after skipping whitespaces and comments)
+    start: Location,
 }
 
 /// Scanner implementation
@@ -161,11 +180,12 @@ impl<'a> Scanner<'a> {
     pub fn new(src: &'a str) -> Self {
         Self {
             chars: self::hidden::CharReader::new(src),
+            start: Location::initial(),
         }
     }
 
-    fn add_context(&mut self, token: TokenKind, pos: Location) -> Token {
-        Token::new(token, pos, self.chars.lexeme().to_string())
+    fn add_context(&mut self, token: TokenKind) -> Token {
+        Token::new(token, self.start, self.chars.lexeme().to_string())
     }
 
     /// Tokenizes a string
@@ -173,10 +193,9 @@ impl<'a> Scanner<'a> {
         let mut tks = Vec::<Token>::new();
         let mut errs = Vec::<ScanError>::new();
         loop {
-            let pos = self.chars.pos();
             match self.next_token() {
                 Ok(Some(tk)) => {
-                    tks.push(self.add_context(tk, pos));
+                    tks.push(self.add_context(tk));
                 }
                 Ok(None) => {
                     // EoF
@@ -194,6 +213,7 @@ impl<'a> Scanner<'a> {
     fn next_token(&mut self) -> Result<Option<TokenKind>> {
         loop {
             self.chars.clear_lexeme();
+            self.start = self.chars.pos();
 
             let c = match self.chars.next() {
                 None => return Ok(None),
@@ -207,12 +227,12 @@ impl<'a> Scanner<'a> {
                 ')' => RightParen,
                 '{' => LeftBrace,
                 '}' => RightBrace,
+                '[' => LeftBracket,
+                ']' => RightBracket,
                 ',' => Comma,
-                '.' => Dot,
-                '+' => Plus,
-                '-' => Minus,
+                ':' => Colon,
+                '.' => self.scan_cmp('.', DotDot, Dot)?,
                 ';' => Semicolon,
-                '*' => Star,
                 '@' => Self_,
 
                 // comparison
@@ -221,11 +241,16 @@ impl<'a> Scanner<'a> {
                 '<' => self.scan_cmp('=', LessEq, Less)?,
                 '>' => self.scan_cmp('=', GreaterEq, Greater)?,
 
+                // arithmetic (or compound assignment)
+                '+' => self.scan_cmp('=', PlusEq, Plus)?,
+                '-' => self.scan_cmp('=', MinusEq, Minus)?,
+                '*' => self.scan_cmp('=', StarEq, Star)?,
+
                 // commenting or division
-                '/' => {
-                    self.scan_slash()?;
-                    continue;
-                }
+                '/' => match self.scan_slash()? {
+                    Some(tk) => tk,
+                    None => continue,
+                },
 
                 // logic
                 '|' => self.scan_logic('|', Or)?,
@@ -239,7 +264,7 @@ impl<'a> Scanner<'a> {
                 c if char_ext::is_digit(c) => self.scan_number()?,
                 c if char_ext::is_alpha(c) => self.scan_kwd_or_ident()?,
 
-                _ => return Err(ScanError::UnexpectedCharacter(c, self.chars.pos())),
+                _ => return Err(ScanError::UnexpectedCharacter(c, self.start)),
             }));
         }
     }
@@ -268,18 +293,20 @@ impl<'a> Scanner<'a> {
     fn scan_logic(&mut self, expected: char, if_true: TokenKind) -> Result<TokenKind> {
         match self.chars.next() {
             Some(c) if c == expected => Ok(if_true),
-            Some(c) => Err(ScanError::UnexpectedCharacter(c, self.chars.pos())),
+            Some(c) => Err(ScanError::UnexpectedCharacter(c, self.start)),
             None => Err(ScanError::UnexpectedEof(self.chars.pos())),
         }
     }
 
-    /// slash (`Ok(TokenKind::Slash)`), comment (`Ok(None)`) or `Err`
+    /// slash (`Ok(Some(TokenKind::Slash))`), `/=`, comment (`Ok(None)`) or `Err`
     fn scan_slash(&mut self) -> Result<Option<TokenKind>> {
         if self.chars.consume_char('/') {
             self.chars.advance_until(|c| c == '\n');
             Ok(None)
         } else if self.chars.consume_char('*') {
             self.scan_range_comment().map(|_| None)
+        } else if self.chars.consume_char('=') {
+            Ok(Some(TokenKind::SlashEq))
         } else {
             Ok(Some(TokenKind::Slash))
         }
@@ -300,7 +327,7 @@ impl<'a> Scanner<'a> {
                 }
             }
         }
-        Err(ScanError::UnterminatedRangeComment(self.chars.pos()))
+        Err(ScanError::UnterminatedRangeComment(self.start))
     }
 
     // TODO: enable rich enclosure such as r#"raw_string"#
@@ -308,7 +335,7 @@ impl<'a> Scanner<'a> {
     fn scan_string(&mut self) -> Result<TokenKind> {
         loop {
             match self.chars.next() {
-                None => return Err(ScanError::UnterminatedString(self.chars.pos())),
+                None => return Err(ScanError::UnterminatedString(self.start)),
                 Some('"') => {
                     // strip " characters
                     return Ok(TokenKind::Str(
@@ -347,18 +374,25 @@ impl<'a> Scanner<'a> {
         use TokenKind::*;
         Ok(match self.chars.lexeme().as_ref() {
             "and" => And,
+            "break" => Break,
+            "catch" => Catch,
             "class" => Class,
+            "continue" => Continue,
             "else" => Else,
             "false" => False,
+            "finally" => Finally,
             "for" => For,
             "fn" => Fn,
             "if" => If,
+            "in" => In,
             "nil" => Nil,
             "or" => Or,
             "print" => Print,
             "return" => Return,
             "super" => Super,
+            "throw" => Throw,
             "true" => True,
+            "try" => Try,
             "var" => Var,
             "while" => While,
             name => Ident(name.to_string()),
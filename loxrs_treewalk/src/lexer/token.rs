@@ -29,12 +29,15 @@ pub enum TokenKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
+    /// `..` for ranges
+    DotDot,
     Minus,
     Plus,
-    // MinusEqual,
-    // PlusEqual,
     Semicolon,
     // slash / star vs mul / div
     Slash,
@@ -49,6 +52,10 @@ pub enum TokenKind {
     GreaterEq,
     Less,
     LessEq,
+    MinusEq,
+    PlusEq,
+    SlashEq,
+    StarEq,
 
     Ident(Identifier),
     // yeah this is terrible
@@ -57,24 +64,32 @@ pub enum TokenKind {
 
     // keywords
     And,
+    Break,
+    Catch,
     Class,
+    Continue,
     Self_,
     Else,
     False,
+    Finally,
     Fn,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
     Return,
     Super,
+    Throw,
     True,
+    Try,
     Var,
     While,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// One-based source position of a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Location {
     ln: usize,
     col: usize,
@@ -113,7 +128,98 @@ impl Location {
     }
 }
 
-use std::fmt::{Debug, Formatter, Result};
+use std::fmt::{Debug, Display, Formatter, Result};
+
+/// `ln:col`
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "{}:{}", self.ln, self.col)
+    }
+}
+
+/// How the token looks like in source code (for error messages)
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        use TokenKind::*;
+        match self {
+            Ident(name) => write!(f, "`{}`", name),
+            Str(s) => write!(f, "`\"{}\"`", s),
+            Num(n) => write!(f, "`{}`", n),
+            kind => write!(f, "`{}`", kind.symbol()),
+        }
+    }
+}
+
+impl TokenKind {
+    /// Describes the kind of token, ignoring the content of identifiers and literals
+    pub fn describe(&self) -> String {
+        use TokenKind::*;
+        match self {
+            Ident(_) => "identifier".to_string(),
+            Str(_) => "string".to_string(),
+            Num(_) => "number".to_string(),
+            kind => format!("`{}`", kind.symbol()),
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        use TokenKind::*;
+        match self {
+            LeftParen => "(",
+            RightParen => ")",
+            LeftBrace => "{",
+            RightBrace => "}",
+            LeftBracket => "[",
+            RightBracket => "]",
+            Comma => ",",
+            Colon => ":",
+            Dot => ".",
+            DotDot => "..",
+            Minus => "-",
+            Plus => "+",
+            Semicolon => ";",
+            Slash => "/",
+            Star => "*",
+            Bang => "!",
+            BangEq => "!=",
+            Eq => "=",
+            EqEq => "==",
+            Greater => ">",
+            GreaterEq => ">=",
+            Less => "<",
+            LessEq => "<=",
+            MinusEq => "-=",
+            PlusEq => "+=",
+            SlashEq => "/=",
+            StarEq => "*=",
+            Ident(_) | Str(_) | Num(_) => "",
+            And => "&&",
+            Break => "break",
+            Catch => "catch",
+            Class => "class",
+            Continue => "continue",
+            Self_ => "@",
+            Else => "else",
+            False => "false",
+            Finally => "finally",
+            Fn => "fn",
+            For => "for",
+            If => "if",
+            In => "in",
+            Nil => "nil",
+            Or => "||",
+            Print => "print",
+            Return => "return",
+            Super => "super",
+            Throw => "throw",
+            True => "true",
+            Try => "try",
+            Var => "var",
+            While => "while",
+        }
+    }
+}
+
 impl Debug for Token {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         write!(
@@ -3,5 +3,14 @@
 mod analizer;
 mod ast;
 pub mod cli;
+mod diagnostics;
+pub mod embed;
+pub mod golden;
 mod lexer;
-mod runtime;
+pub mod runtime;
+
+pub use embed::{Lox, LoxError};
+pub use runtime::{
+    obj::{LoxObj, LoxValue},
+    output::SharedBuffer,
+};
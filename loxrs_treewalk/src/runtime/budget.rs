@@ -0,0 +1,80 @@
+//! Limits on running untrusted scripts in the tree-walking `Interpreter`
+//!
+//! The `Interpreter` takes a step before visiting each statement and expression, and on each
+//! iteration of a `for` loop, whose body can be empty. Errors from the budget are not catchable
+//! with `try`, so a script can't keep itself running.
+
+use crate::runtime::{Result, RuntimeErrorKind};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Instant;
+
+/// Steps between checks of the clock and the interrupt flag. Visiting a node is slow, so a deadline
+/// or an interrupt is still noticed well within a millisecond.
+const CHECK_INTERVAL: u64 = 256;
+
+/// Fuel, deadline and interrupt flag of an `Interpreter`. Unlimited by default.
+#[derive(Debug, Default)]
+pub struct Budget {
+    /// Remaining steps
+    fuel: Option<u64>,
+    deadline: Option<Instant>,
+    /// Set by the host (possibly from another thread) to stop the execution
+    interrupt: Arc<AtomicBool>,
+    /// Steps until the next check of the clock and the interrupt flag
+    until_check: u64,
+}
+
+impl Budget {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.deadline = deadline;
+    }
+
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    /// Consumes a step, failing if the budget is exhausted
+    #[inline]
+    pub fn step(&mut self) -> Result<()> {
+        if let Some(fuel) = self.fuel.as_mut() {
+            if *fuel == 0 {
+                return Err(RuntimeErrorKind::OutOfFuel.into());
+            }
+            *fuel -= 1;
+        }
+        if self.until_check == 0 {
+            self.until_check = CHECK_INTERVAL;
+            self.check()?;
+        }
+        self.until_check -= 1;
+        Ok(())
+    }
+
+    fn check(&self) -> Result<()> {
+        // the flag is cleared so that the host can run scripts again
+        if self.interrupt.swap(false, Ordering::Relaxed) {
+            return Err(RuntimeErrorKind::Interrupted.into());
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                Err(RuntimeErrorKind::DeadlineExceeded.into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
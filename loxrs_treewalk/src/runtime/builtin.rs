@@ -0,0 +1,153 @@
+//! Methods of built-in types such as lists and maps
+
+use crate::ast::pretty_printer::PrettyPrint;
+use crate::runtime::{
+    obj::{LoxKey, LoxMap, LoxObj, LoxValue},
+    Result, RuntimeErrorKind,
+};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+type LoxList = Rc<RefCell<Vec<LoxObj>>>;
+
+const LIST_METHODS: &[&str] = &["len", "push", "pop", "insert", "slice"];
+const MAP_METHODS: &[&str] = &["len", "has", "remove", "keys", "values"];
+
+/// Method of a built-in type bound to the receiver
+#[derive(Clone, Debug)]
+pub struct LoxBuiltinMethod {
+    pub receiver: Box<LoxObj>,
+    pub name: String,
+}
+
+impl LoxBuiltinMethod {
+    /// Looks up the method of the receiver
+    pub fn bind(receiver: &LoxObj, name: &str) -> Result<Self> {
+        let methods = match receiver {
+            LoxObj::List(_) => LIST_METHODS,
+            LoxObj::Map(_) => MAP_METHODS,
+            _ => return Err(RuntimeErrorKind::NotForDotOperator.into()),
+        };
+        if !methods.contains(&name) {
+            return Err(RuntimeErrorKind::NoMethodWithName(name.to_string()).into());
+        }
+        Ok(Self {
+            receiver: Box::new(receiver.clone()),
+            name: name.to_string(),
+        })
+    }
+
+    pub fn call(&self, args: &[LoxObj]) -> Result<LoxObj> {
+        match *self.receiver {
+            LoxObj::List(ref list) => self::call_list_method(list, &self.name, args),
+            LoxObj::Map(ref map) => self::call_map_method(map, &self.name, args),
+            _ => Err(RuntimeErrorKind::NotForDotOperator.into()),
+        }
+    }
+}
+
+fn ensure_arity(args: &[LoxObj], n: usize) -> Result<()> {
+    if args.len() != n {
+        Err(RuntimeErrorKind::WrongNumberOfArguments.into())
+    } else {
+        Ok(())
+    }
+}
+
+fn call_list_method(list: &LoxList, name: &str, args: &[LoxObj]) -> Result<LoxObj> {
+    match name {
+        "len" => {
+            self::ensure_arity(args, 0)?;
+            Ok(LoxValue::Number(list.borrow().len() as f64).into())
+        }
+        "push" => {
+            self::ensure_arity(args, 1)?;
+            list.borrow_mut().push(args[0].clone());
+            Ok(LoxObj::nil())
+        }
+        "pop" => {
+            self::ensure_arity(args, 0)?;
+            list.borrow_mut()
+                .pop()
+                .ok_or_else(|| RuntimeErrorKind::PopFromEmptyList.into())
+        }
+        "insert" => {
+            self::ensure_arity(args, 2)?;
+            let mut list = list.borrow_mut();
+            let i = self::to_position(&args[0], list.len())?;
+            list.insert(i, args[1].clone());
+            Ok(LoxObj::nil())
+        }
+        "slice" => {
+            self::ensure_arity(args, 2)?;
+            let list = list.borrow();
+            let from = self::to_position(&args[0], list.len())?;
+            let to = self::to_position(&args[1], list.len())?;
+            if from > to {
+                return Err(RuntimeErrorKind::InvalidSlice(from, to).into());
+            }
+            Ok(LoxObj::list(list[from..to].to_vec()))
+        }
+        _ => Err(RuntimeErrorKind::NoMethodWithName(name.to_string()).into()),
+    }
+}
+
+fn call_map_method(map: &Rc<RefCell<LoxMap>>, name: &str, args: &[LoxObj]) -> Result<LoxObj> {
+    match name {
+        "len" => {
+            self::ensure_arity(args, 0)?;
+            Ok(LoxValue::Number(map.borrow().len() as f64).into())
+        }
+        "has" => {
+            self::ensure_arity(args, 1)?;
+            let key = LoxKey::from_obj(&args[0])?;
+            Ok(LoxObj::bool(map.borrow().contains_key(&key)))
+        }
+        "remove" => {
+            // returns the removed value or `nil`
+            self::ensure_arity(args, 1)?;
+            let key = LoxKey::from_obj(&args[0])?;
+            Ok(map.borrow_mut().remove(&key).unwrap_or_else(LoxObj::nil))
+        }
+        "keys" => {
+            self::ensure_arity(args, 0)?;
+            let keys = map.borrow().keys().map(|k| k.to_value().into()).collect();
+            Ok(LoxObj::list(keys))
+        }
+        "values" => {
+            self::ensure_arity(args, 0)?;
+            let values = map.borrow().values().cloned().collect();
+            Ok(LoxObj::list(values))
+        }
+        _ => Err(RuntimeErrorKind::NoMethodWithName(name.to_string()).into()),
+    }
+}
+
+/// Looks up a map entry failing if the key is not in the map
+pub fn map_get(map: &LoxMap, key: &LoxKey) -> Result<LoxObj> {
+    map.get(key)
+        .cloned()
+        .ok_or_else(|| RuntimeErrorKind::NoEntryForKey(key.to_value().pretty_print()).into())
+}
+
+/// Converts a Lox object into an index less than `len`
+pub fn to_index(index: &LoxObj, len: usize) -> Result<usize> {
+    match self::to_position(index, len)? {
+        i if i == len => Err(RuntimeErrorKind::IndexOutOfBounds(i, len).into()),
+        i => Ok(i),
+    }
+}
+
+/// Converts a position between items (to insert at or to slice), which can be the length
+fn to_position(index: &LoxObj, len: usize) -> Result<usize> {
+    let n = index.as_num().ok_or(RuntimeErrorKind::MismatchedType)?;
+    if n < 0.0 {
+        Err(RuntimeErrorKind::NegativeIndex(n).into())
+    } else if n.fract() != 0.0 {
+        Err(RuntimeErrorKind::NonIntegerIndex(n).into())
+    } else if n as usize > len {
+        Err(RuntimeErrorKind::IndexOutOfBounds(n as usize, len).into())
+    } else {
+        Ok(n as usize)
+    }
+}
@@ -1,30 +1,48 @@
-use crate::runtime::{obj::LoxObj, RuntimeError};
+use crate::ast::expr::LocalSlot;
+use crate::runtime::{obj::LoxObj, RuntimeError, RuntimeErrorKind};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::rc::{Rc, Weak};
+use std::rc::Rc;
 
 type Result<T> = ::std::result::Result<T, RuntimeError>;
 
+/// Scope of variables
+///
+/// The global `Env` (the one without parent) looks up variables by name. Local variables are
+/// stored in slots in order of declaration, and they're accessed with indices computed by the
+/// `Resolver`.
+///
+/// An `Env` owns its parent so that closures keep the scopes they captured alive.
 #[derive(Clone, Debug)]
 pub struct Env {
-    /// Objects; variables or functions
+    /// Global objects; variables or functions
     map: RefCell<HashMap<String, LoxObj>>,
+    /// Local objects
+    slots: Vec<LoxObj>,
     /// Enclosing environment (if any)
-    parent: Weak<RefCell<Self>>,
+    parent: Option<Rc<RefCell<Self>>>,
+}
+
+impl Default for Env {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Env {
     pub fn new() -> Self {
         Env {
             map: RefCell::new(HashMap::new()),
-            parent: Weak::new(),
+            slots: Vec::new(),
+            parent: None,
         }
     }
 
     pub fn from_parent(parent: &Rc<RefCell<Self>>) -> Self {
         Env {
             map: RefCell::new(HashMap::new()),
-            parent: Rc::downgrade(parent),
+            slots: Vec::new(),
+            parent: Some(Rc::clone(parent)),
         }
     }
 
@@ -34,9 +52,9 @@ impl Env {
     pub fn get(&self, name: &str) -> Result<LoxObj> {
         match self.map.borrow().get(name) {
             Some(obj) => Ok(obj.clone()),
-            None => match self.parent.upgrade() {
-                Some(parent) => parent.borrow().get(name),
-                None => Err(RuntimeError::Undefined(name.to_string())),
+            None => match self.parent {
+                Some(ref parent) => parent.borrow().get(name),
+                None => Err(RuntimeErrorKind::Undefined(name.to_string()).into()),
             },
         }
     }
@@ -46,58 +64,84 @@ impl Env {
         self.map.borrow().get(name).is_some()
     }
 
+    /// Defines a global variable by name or a local variable in the next slot
     pub fn define(&mut self, name: &str, obj: LoxObj) -> Result<()> {
-        if self.map.borrow().contains_key(name) {
+        if self.parent.is_some() {
+            // the `Resolver` rejects duplicate local variables
+            self.slots.push(obj);
+            Ok(())
+        } else if self.map.borrow().contains_key(name) {
             // we disable overwriting a previous variable with same name
-            Err(RuntimeError::DuplicateDeclaration(name.to_string()))
+            Err(RuntimeErrorKind::DuplicateDeclaration(name.to_string()).into())
         } else {
             self.map.borrow_mut().insert(name.to_owned(), obj);
             Ok(())
         }
     }
 
+    pub fn parent(&self) -> Option<&Rc<RefCell<Self>>> {
+        self.parent.as_ref()
+    }
+
+    /// Visits the variables in this environment. Returns false if they're being mutated.
+    pub fn try_for_each(&self, f: &mut dyn FnMut(&LoxObj)) -> bool {
+        match self.map.try_borrow() {
+            Ok(map) => {
+                map.values().chain(self.slots.iter()).for_each(f);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Removes all the variables, which breaks reference cycles through this environment
+    pub fn clear(&mut self) {
+        self.map.get_mut().clear();
+        self.slots.clear();
+    }
+
     pub fn assign(&mut self, name: &str, obj: LoxObj) -> Result<()> {
         let mut map = self.map.borrow_mut();
         if map.contains_key(name) {
             map.insert(name.to_owned(), obj);
             Ok(())
         } else {
-            match self.parent.upgrade() {
-                Some(rc) => rc.borrow_mut().assign(name, obj),
-                None => Err(RuntimeError::Undefined(name.to_string())),
+            match self.parent {
+                Some(ref rc) => rc.borrow_mut().assign(name, obj),
+                None => Err(RuntimeErrorKind::Undefined(name.to_string()).into()),
             }
         }
     }
 }
 
 /// Efficient methods trusting Resolver's work
+///
+/// Local variables are always defined before they're resolved, so the slots exist.
 impl Env {
-    pub fn get_resolved(&self, name: &str, d: usize) -> Result<LoxObj> {
-        if d == 0 {
-            self.get(name)
+    pub fn get_resolved(&self, slot: LocalSlot) -> LoxObj {
+        if slot.depth == 0 {
+            self.slots[slot.index].clone()
         } else {
-            match self.ancestor(d).borrow().map.borrow().get(name) {
-                Some(obj) => Ok(obj.clone()),
-                _ => Err(RuntimeError::Undefined(name.to_string())),
-            }
+            let parent = self.parent.as_ref().unwrap().borrow();
+            parent.get_resolved(LocalSlot {
+                depth: slot.depth - 1,
+                ..slot
+            })
         }
     }
 
-    pub fn assign_resolved(&mut self, name: &str, obj: LoxObj, d: usize) -> Result<()> {
-        if d == 0 {
-            self.assign(name, obj)
+    pub fn assign_resolved(&mut self, slot: LocalSlot, obj: LoxObj) {
+        if slot.depth == 0 {
+            self.slots[slot.index] = obj;
         } else {
-            self.ancestor(d).borrow_mut().assign(name, obj)
-        }
-    }
-
-    /// Looks up an enclosing environment in a distance, trusting the length > 0.
-    fn ancestor(&self, d: usize) -> Rc<RefCell<Env>> {
-        let mut env = self.parent.upgrade().unwrap();
-        for _ in 0..(d - 1) {
-            let next = env.borrow().parent.upgrade().unwrap();
-            env = next;
+            let mut parent = self.parent.as_ref().unwrap().borrow_mut();
+            parent.assign_resolved(
+                LocalSlot {
+                    depth: slot.depth - 1,
+                    ..slot
+                },
+                obj,
+            );
         }
-        env
     }
 }
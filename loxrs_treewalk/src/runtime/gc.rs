@@ -0,0 +1,332 @@
+//! Cycle collector for runtime objects
+//!
+//! Objects are reference counted, so they're freed as soon as they become unreachable, except for
+//! reference cycles (e.g. an instance storing its own bound method, or a closure stored in the
+//! scope it captures). `Heap` tracks the containers (scopes, instances, lists, maps and classes)
+//! and frees such cycles by trial deletion:
+//!
+//! 1. For each tracked object, count the references from the other tracked objects.
+//! 2. Objects with more references than that are referred to from outside, e.g. from the
+//!    interpreter, the Rust stack or the host. They're the roots.
+//! 3. Objects unreachable from the roots are garbage; we clear their contents to break the cycles.
+//!
+//! So collection is safe at any time, and untracked objects only make it conservative.
+
+use crate::runtime::{
+    env::Env,
+    obj::{LoxClass, LoxFn, LoxInstance, LoxMap, LoxObj},
+};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+/// The number of tracked objects to trigger the first collection
+const INITIAL_THRESHOLD: usize = 1024;
+
+/// Statistics of the cycle collector
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GcStats {
+    /// The number of objects tracked (some of them may be already freed)
+    pub tracked: usize,
+    /// The number of collections run so far
+    pub collections: usize,
+    /// The number of objects freed by the collector so far
+    pub collected: usize,
+}
+
+/// Tracked objects
+#[derive(Debug)]
+pub struct Heap {
+    objs: HashMap<usize, WeakNode>,
+    /// Collects garbage when the number of tracked objects exceeds it
+    threshold: usize,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            objs: HashMap::new(),
+            threshold: INITIAL_THRESHOLD,
+            stats: GcStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            tracked: self.objs.len(),
+            ..self.stats.clone()
+        }
+    }
+
+    pub fn track_env(&mut self, env: &Rc<RefCell<Env>>) {
+        self.insert(Node::Env(Rc::clone(env)));
+    }
+
+    /// Tracks the object if it's a container (or a function closed over a scope)
+    pub fn track(&mut self, obj: &LoxObj) {
+        let node = match obj {
+            LoxObj::Value(_) => return,
+            LoxObj::Callable(LoxFn::User(f)) => Node::Env(Rc::clone(&f.closure)),
+            LoxObj::Callable(_) => return,
+            LoxObj::Class(class) => Node::Class(Rc::clone(class)),
+            LoxObj::Instance(instance) => Node::Instance(Rc::clone(instance)),
+            LoxObj::List(list) => Node::List(Rc::clone(list)),
+            LoxObj::Map(map) => Node::Map(Rc::clone(map)),
+        };
+        self.insert(node);
+    }
+
+    fn insert(&mut self, node: Node) {
+        self.objs
+            .entry(node.id())
+            .or_insert_with(|| node.downgrade());
+        if self.objs.len() > self.threshold {
+            self.collect();
+        }
+    }
+
+    /// Frees unreachable reference cycles and returns the number of objects freed
+    pub fn collect(&mut self) -> usize {
+        // forget freed objects
+        let mut nodes = Vec::with_capacity(self.objs.len());
+        self.objs.retain(|_, weak| match weak.upgrade() {
+            Some(node) => {
+                nodes.push(node);
+                true
+            }
+            None => false,
+        });
+        let index = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id(), i))
+            .collect::<HashMap<_, _>>();
+
+        // count references from outside of the tracked objects (except ours in `nodes`)
+        let mut external = nodes
+            .iter()
+            .map(|node| node.strong_count() - 1)
+            .collect::<Vec<_>>();
+        for node in nodes.iter() {
+            let visited = node.children(&mut |id| {
+                if let Some(&i) = index.get(&id) {
+                    external[i] -= 1;
+                }
+            });
+            if !visited {
+                // the node is being mutated, so it must be in use
+                external[index[&node.id()]] += 1;
+            }
+        }
+
+        // mark objects reachable from the roots
+        let mut reachable = vec![false; nodes.len()];
+        let mut stack = (0..nodes.len())
+            .filter(|&i| external[i] > 0)
+            .collect::<Vec<_>>();
+        while let Some(i) = stack.pop() {
+            if reachable[i] {
+                continue;
+            }
+            reachable[i] = true;
+            nodes[i].children(&mut |id| {
+                if let Some(&j) = index.get(&id) {
+                    if !reachable[j] {
+                        stack.push(j);
+                    }
+                }
+            });
+        }
+
+        // break the cycles
+        let mut n_collected = 0;
+        for (node, _) in nodes.iter().zip(reachable.iter()).filter(|(_, r)| !**r) {
+            node.clear();
+            self.objs.remove(&node.id());
+            n_collected += 1;
+        }
+        drop(nodes);
+
+        self.stats.collections += 1;
+        self.stats.collected += n_collected;
+        self.threshold = INITIAL_THRESHOLD.max(self.objs.len() * 2);
+        n_collected
+    }
+}
+
+/// Strong reference to a tracked object
+enum Node {
+    Env(Rc<RefCell<Env>>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    List(Rc<RefCell<Vec<LoxObj>>>),
+    Map(Rc<RefCell<LoxMap>>),
+    Class(Rc<LoxClass>),
+}
+
+/// Weak reference to a tracked object
+#[derive(Debug)]
+enum WeakNode {
+    Env(Weak<RefCell<Env>>),
+    Instance(Weak<RefCell<LoxInstance>>),
+    List(Weak<RefCell<Vec<LoxObj>>>),
+    Map(Weak<RefCell<LoxMap>>),
+    Class(Weak<LoxClass>),
+}
+
+impl WeakNode {
+    fn upgrade(&self) -> Option<Node> {
+        Some(match self {
+            WeakNode::Env(env) => Node::Env(env.upgrade()?),
+            WeakNode::Instance(instance) => Node::Instance(instance.upgrade()?),
+            WeakNode::List(list) => Node::List(list.upgrade()?),
+            WeakNode::Map(map) => Node::Map(map.upgrade()?),
+            WeakNode::Class(class) => Node::Class(class.upgrade()?),
+        })
+    }
+}
+
+/// Identity of an `Rc`
+fn id<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+impl Node {
+    fn id(&self) -> usize {
+        match self {
+            Node::Env(env) => self::id(env),
+            Node::Instance(instance) => self::id(instance),
+            Node::List(list) => self::id(list),
+            Node::Map(map) => self::id(map),
+            Node::Class(class) => self::id(class),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        match self {
+            Node::Env(env) => Rc::strong_count(env),
+            Node::Instance(instance) => Rc::strong_count(instance),
+            Node::List(list) => Rc::strong_count(list),
+            Node::Map(map) => Rc::strong_count(map),
+            Node::Class(class) => Rc::strong_count(class),
+        }
+    }
+
+    fn downgrade(&self) -> WeakNode {
+        match self {
+            Node::Env(env) => WeakNode::Env(Rc::downgrade(env)),
+            Node::Instance(instance) => WeakNode::Instance(Rc::downgrade(instance)),
+            Node::List(list) => WeakNode::List(Rc::downgrade(list)),
+            Node::Map(map) => WeakNode::Map(Rc::downgrade(map)),
+            Node::Class(class) => WeakNode::Class(Rc::downgrade(class)),
+        }
+    }
+
+    /// Visits the identities of the objects this node refers to (one per reference). Returns
+    /// false if the node is being mutated and can't be visited.
+    fn children(&self, f: &mut dyn FnMut(usize)) -> bool {
+        match self {
+            Node::Env(env) => {
+                let env = match env.try_borrow() {
+                    Ok(env) => env,
+                    Err(_) => return false,
+                };
+                if let Some(parent) = env.parent() {
+                    f(self::id(parent));
+                }
+                env.try_for_each(&mut |obj| self::obj_children(obj, f))
+            }
+            Node::Instance(instance) => match instance.try_borrow() {
+                Ok(instance) => {
+                    f(self::id(&instance.class));
+                    instance
+                        .fields()
+                        .values()
+                        .for_each(|obj| self::obj_children(obj, f));
+                    true
+                }
+                Err(_) => false,
+            },
+            Node::List(list) => match list.try_borrow() {
+                Ok(list) => {
+                    list.iter().for_each(|obj| self::obj_children(obj, f));
+                    true
+                }
+                Err(_) => false,
+            },
+            Node::Map(map) => match map.try_borrow() {
+                Ok(map) => {
+                    map.values().for_each(|obj| self::obj_children(obj, f));
+                    true
+                }
+                Err(_) => false,
+            },
+            Node::Class(class) => {
+                if let Some(superclass) = class.superclass.as_ref() {
+                    f(self::id(superclass));
+                }
+                class
+                    .methods
+                    .values()
+                    .for_each(|method| self::fn_children(method, f));
+                true
+            }
+        }
+    }
+
+    /// Drops the references this node holds (classes are freed through the scopes they're in)
+    fn clear(&self) {
+        match self {
+            Node::Env(env) => {
+                if let Ok(mut env) = env.try_borrow_mut() {
+                    env.clear();
+                }
+            }
+            Node::Instance(instance) => {
+                let fields = instance
+                    .try_borrow_mut()
+                    .map(|mut instance| instance.take_fields());
+                drop(fields);
+            }
+            Node::List(list) => {
+                // the items are dropped after releasing the borrow
+                let items = list
+                    .try_borrow_mut()
+                    .map(|mut list| std::mem::take(&mut *list));
+                drop(items);
+            }
+            Node::Map(map) => {
+                let entries = map
+                    .try_borrow_mut()
+                    .map(|mut map| std::mem::take(&mut *map));
+                drop(entries);
+            }
+            Node::Class(_) => {}
+        }
+    }
+}
+
+fn obj_children(obj: &LoxObj, f: &mut dyn FnMut(usize)) {
+    match obj {
+        LoxObj::Value(_) => {}
+        LoxObj::Callable(fn_obj) => self::fn_children(fn_obj, f),
+        LoxObj::Class(class) => f(self::id(class)),
+        LoxObj::Instance(instance) => f(self::id(instance)),
+        LoxObj::List(list) => f(self::id(list)),
+        LoxObj::Map(map) => f(self::id(map)),
+    }
+}
+
+fn fn_children(fn_obj: &LoxFn, f: &mut dyn FnMut(usize)) {
+    match fn_obj {
+        LoxFn::User(user) => f(self::id(&user.closure)),
+        LoxFn::Builtin(method) => self::obj_children(&method.receiver, f),
+        LoxFn::Native(_) => {}
+    }
+}
@@ -1,16 +1,55 @@
 use std::cell::RefCell;
-use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::io::{self, Write};
 use std::rc::Rc;
-use std::time::SystemTime;
+use std::sync::{atomic::AtomicBool, Arc};
+use std::thread;
+use std::time::{Instant, SystemTime};
 
 use crate::ast::{expr::*, stmt::*, ExprVisitor, PrettyPrint, StmtVisitor};
+use crate::lexer::token::Location;
+use crate::runtime::budget::Budget;
 use crate::runtime::env::Env;
+use crate::runtime::gc::{GcStats, Heap};
 use crate::runtime::{
-    obj::{LoxClass, LoxFn, LoxInstance, LoxObj, LoxUserFn, LoxValue},
-    Result, RuntimeError,
+    builtin::{self, LoxBuiltinMethod},
+    obj::{LoxClass, LoxFn, LoxInstance, LoxKey, LoxMap, LoxNativeFn, LoxObj, LoxUserFn, LoxValue},
+    Result, RuntimeError, RuntimeErrorKind,
 };
 
+/// How a statement interrupted the execution of the enclosing statements
+#[derive(Clone, Debug)]
+pub enum Flow {
+    Return(LoxObj),
+    Break,
+    Continue,
+}
+
+/// Rust stack size of the thread the CLI and golden-file tests run scripts on
+pub const SCRIPT_STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// The default limit of nested function calls. Running low on the Rust stack fails with a stack
+/// overflow error too, so the limit only makes deep recursion fail the same way on any stack.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 10_000;
+
+/// Rust stack left unused for the frames between two checks of the remaining stack
+const STACK_RED_ZONE: usize = 256 * 1024;
+
+/// Runs a function on a new thread with a stack of [`SCRIPT_STACK_SIZE`], so that scripts can
+/// recurse deeply before running out of the stack
+pub fn on_script_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        let handle = thread::Builder::new()
+            .stack_size(SCRIPT_STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("failed to spawn a thread for running scripts");
+        match handle.join() {
+            Ok(x) => x,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    })
+}
+
 // TODO: encapsulate `Rc<Refcell<T>>`
 pub struct Interpreter {
     /// Points at a global `Env`
@@ -19,110 +58,387 @@ pub struct Interpreter {
     pub env: Rc<RefCell<Env>>,
     /// The time interpretation started. Required for `clock` native function.
     begin_time: SystemTime,
-    /// Maps each identifier in local scope to the distance to the scope it's in.
-    pub caches: HashMap<VarUseData, usize>,
+    /// Where `print` writes to
+    out: Box<dyn Write>,
+    /// Objects created while interpreting, collected to free reference cycles
+    heap: Heap,
+    /// The number of user functions being called
+    call_depth: usize,
+    max_call_depth: usize,
+    /// Limits on steps and time
+    budget: Budget,
+    /// The object being thrown with `throw`, taken when it's caught
+    thrown: Option<LoxObj>,
+    /// The class of built-in errors caught with `try`
+    error_class: Rc<LoxClass>,
+}
+
+impl Drop for Interpreter {
+    /// Frees reference cycles made by the program, e.g. global functions stored in the scope they
+    /// capture. Objects still referred to by the host are kept alive.
+    fn drop(&mut self) {
+        let globals = std::mem::replace(&mut self.globals, Rc::new(RefCell::new(Env::new())));
+        self.env = Rc::clone(&self.globals);
+        drop(globals);
+        self.heap.collect();
+    }
 }
 
 /// Capabilities provided by `Resolver`
 impl Interpreter {
     fn lookup_resolved(&self, var: &VarUseData) -> Result<LoxObj> {
-        if let Some(distance) = self.caches.get(var) {
+        if let Some(slot) = var.slot() {
             // it's a local variable resoled
-            self.env.borrow().get_resolved(&var.name, distance.clone())
+            Ok(self.env.borrow().get_resolved(slot))
         } else {
             // we assume it's a global variables, which are not tracked by the `Resolver`
             self.globals.borrow().get(&var.name)
         }
     }
+
+    fn assign_resolved(&self, var: &VarUseData, obj: LoxObj) -> Result<()> {
+        if let Some(slot) = var.slot() {
+            self.env.borrow_mut().assign_resolved(slot, obj);
+            Ok(())
+        } else {
+            self.globals.borrow_mut().assign(&var.name, obj)
+        }
+    }
+
+    fn lookup_superclass(&self, var: &VarUseData) -> Result<Rc<LoxClass>> {
+        match self.lookup_resolved(var)? {
+            LoxObj::Class(class) => Ok(class),
+            _ => Err(RuntimeErrorKind::SuperclassNotClass(var.name.to_owned()).into()),
+        }
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
+    /// Creates an interpreter which prints to stdout
     pub fn new() -> Self {
-        let globals = Rc::new(RefCell::new(Self::global_env()));
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates an interpreter which prints to the given writer
+    pub fn with_output(out: Box<dyn Write>) -> Self {
+        let globals = Rc::new(RefCell::new(Env::new()));
         let env = Rc::clone(&globals);
-        Self {
+        let mut interpreter = Self {
             globals: globals,
             env: env,
             begin_time: SystemTime::now(),
-            caches: HashMap::new(),
-        }
+            out: out,
+            heap: Heap::new(),
+            call_depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            budget: Budget::new(),
+            thrown: None,
+            error_class: Rc::new(LoxClass {
+                name: "Error".to_owned(),
+                superclass: None,
+                methods: HashMap::new(),
+            }),
+        };
+        interpreter.heap.track_env(&interpreter.globals);
+        let error_class = LoxObj::Class(Rc::clone(&interpreter.error_class));
+        interpreter.set_global("Error", error_class);
+        interpreter
+            .define_native("clock", 0, |interpreter, _args| {
+                Ok(interpreter.native_clock().into())
+            })
+            .unwrap();
+        interpreter
+            .define_native("gc", 0, |interpreter, _args| {
+                let n = interpreter.collect_garbage();
+                Ok(LoxValue::Number(n as f64).into())
+            })
+            .unwrap();
+        interpreter
     }
 
-    /// Creates a new `Env` with native functions
-    fn global_env() -> Env {
-        let mut env = Env::new();
-        env.define("clock", LoxObj::Callable(LoxFn::Clock)).unwrap();
-        env
+    /// Defines a global function implemented in Rust
+    ///
+    /// The number of arguments is checked before calling `f`.
+    pub fn define_native<F>(&mut self, name: &str, arity: usize, f: F) -> Result<()>
+    where
+        F: Fn(&mut Interpreter, &[LoxObj]) -> Result<LoxObj> + 'static,
+    {
+        let native = LoxNativeFn {
+            name: name.to_owned(),
+            arity: arity,
+            body: Rc::new(f),
+        };
+        self.globals
+            .borrow_mut()
+            .define(name, LoxObj::Callable(LoxFn::Native(native)))
+    }
+
+    /// Limits the depth of nested function calls. Deeper calls fail with a stack overflow error
+    /// instead of overflowing the Rust stack.
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    /// Limits the number of steps (visits of statements and expressions) to run. Running out of
+    /// them fails with an error. `None` means unlimited, which is the default.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.budget.set_fuel(fuel);
+    }
+
+    /// The number of steps left (if limited)
+    pub fn fuel(&self) -> Option<u64> {
+        self.budget.fuel()
+    }
+
+    /// Makes the execution fail after the given time
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.budget.set_deadline(deadline);
+    }
+
+    /// A flag another thread can set to stop the execution with an error. It's cleared when the
+    /// error is reported.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.budget.interrupt_handle()
+    }
+
+    /// The output stream of `print`, also available for native functions
+    pub fn out(&mut self) -> &mut dyn Write {
+        &mut *self.out
     }
 
     /// The entry point of statement interpretation
     pub fn interpret(&mut self, stmt: &Stmt) -> Result<Option<LoxObj>> {
-        self.visit_stmt(stmt)
+        let flow = match self.exec(stmt) {
+            Ok(flow) => flow,
+            Err(why) => {
+                // nobody catches it anymore
+                self.thrown = None;
+                return Err(why);
+            }
+        };
+        Ok(match flow {
+            Some(Flow::Return(obj)) => Some(obj),
+            _ => None,
+        })
+    }
+
+    /// The entry point of expression evaluation. The expression is run as an expression statement
+    /// is, except that its value is returned.
+    pub fn interpret_expr(&mut self, expr: &Expr) -> Result<LoxObj> {
+        let result = self
+            .budget
+            .step()
+            .map_err(|why| why.or_at(expr.pos()))
+            .and_then(|()| self.eval_expr(expr));
+        if result.is_err() {
+            // nobody catches it anymore
+            self.thrown = None;
+        }
+        result
+    }
+
+    /// Looks up a global variable
+    pub fn get_global(&self, name: &str) -> Option<LoxObj> {
+        self.globals.borrow().get(name).ok()
+    }
+
+    /// Defines a global variable or overwrites it if it exists
+    pub fn set_global(&mut self, name: &str, obj: LoxObj) {
+        self.heap.track(&obj);
+        let mut globals = self.globals.borrow_mut();
+        if globals.contains(name) {
+            globals.assign(name, obj).unwrap();
+        } else {
+            globals.define(name, obj).unwrap();
+        }
+    }
+
+    /// Interpretes a statement, locating errors
+    fn exec(&mut self, stmt: &Stmt) -> Result<Option<Flow>> {
+        self::check_stack().map_err(|why| why.or_at(stmt.pos()))?;
+        self.budget.step().map_err(|why| why.or_at(stmt.pos()))?;
+        self.visit_stmt(stmt).map_err(|why| why.or_at(stmt.pos()))
     }
 
     /// Interpretes a block of statements
-    fn interpret_stmts(&mut self, stmts: &[Stmt]) -> Result<Option<LoxObj>> {
+    fn interpret_stmts(&mut self, stmts: &[Stmt]) -> Result<Option<Flow>> {
         for stmt in stmts.iter() {
-            if let Some(obj) = self.interpret(stmt)? {
-                return Ok(Some(obj)); // `return` statemenet considered
+            if let Some(flow) = self.exec(stmt)? {
+                return Ok(Some(flow)); // `return`, `break` or `continue` considered
             }
         }
         Ok(None)
     }
 
+    /// Frees unreachable reference cycles and returns the number of objects freed
+    ///
+    /// It's also run automatically as the number of objects grows.
+    pub fn collect_garbage(&mut self) -> usize {
+        self.heap.collect()
+    }
+
+    /// Memory statistics of the cycle collector
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Tracks an object so that it's freed by the cycle collector if it makes a cycle
+    fn track(&mut self, obj: LoxObj) -> LoxObj {
+        self.heap.track(&obj);
+        obj
+    }
+
+    /// Allocates a scope, tracking it for the cycle collector
+    fn alloc_env(&mut self, env: Env) -> Rc<RefCell<Env>> {
+        let env = Rc::new(RefCell::new(env));
+        self.heap.track_env(&env);
+        env
+    }
+
     /// Intepretes a block in a scope
-    fn interpret_stmts_with_scope(&mut self, stmts: &[Stmt], scope: Env) -> Result<Option<LoxObj>> {
+    fn interpret_stmts_with_scope(&mut self, stmts: &[Stmt], scope: Env) -> Result<Option<Flow>> {
         let prev = Rc::clone(&self.env);
-        self.env = Rc::new(RefCell::new(scope));
+        self.env = self.alloc_env(scope);
         let result = self.interpret_stmts(stmts);
         self.env = prev;
         result
     }
 
+    /// Calls a function or constructs an instance of a class with evaluated arguments
+    pub fn call(&mut self, callee: &LoxObj, args: &[LoxObj]) -> Result<LoxObj> {
+        match callee {
+            LoxObj::Callable(ref fn_obj) => {
+                let obj = self.invoke(fn_obj, args)?.unwrap_or_else(|| LoxObj::nil());
+                Ok(obj)
+            }
+            // we treat a class name as a constructor
+            LoxObj::Class(ref class) => {
+                let instance = LoxInstance::new(class);
+                let instance = Rc::new(RefCell::new(instance));
+                self.heap.track(&LoxObj::Instance(Rc::clone(&instance)));
+                // BE CAREFUL NOT TO BORROW TOO LONG!
+                let initializer = instance.borrow().class.find_method("init");
+                match initializer {
+                    Some(LoxFn::User(initializer)) => {
+                        let initializer = initializer.bind(&instance)?;
+                        self.heap.track_env(&initializer.closure);
+                        self.invoke_user_fn(&initializer, args)?;
+                    }
+                    Some(_) => {
+                        return Err(RuntimeErrorKind::InvalidInitializer(class.name.clone()).into())
+                    }
+                    None => {}
+                }
+                Ok(LoxObj::Instance(instance))
+            }
+            _ => Err(RuntimeErrorKind::MismatchedType.into()),
+        }
+    }
+
     /// Invokes a given function object (native or user-defined)
-    pub fn invoke(&mut self, fn_obj: &LoxFn, args: &Args) -> Result<Option<LoxObj>> {
+    pub fn invoke(&mut self, fn_obj: &LoxFn, args: &[LoxObj]) -> Result<Option<LoxObj>> {
         match fn_obj {
             LoxFn::User(ref def) => self.invoke_user_fn(def, args),
-            LoxFn::Clock => {
-                let s = self.native_clock(args)?;
-                Ok(Some(LoxObj::Value(s)))
+            LoxFn::Native(ref native) => {
+                Self::ensure_arities(native.arity, args.len())?;
+                let obj = (native.body)(self, args)?;
+                Ok(Some(self.track(obj)))
+            }
+            LoxFn::Builtin(ref method) => {
+                let obj = method.call(args)?;
+                Ok(Some(self.track(obj)))
             }
         }
     }
 
-    pub fn invoke_user_fn(&mut self, def: &LoxUserFn, args: &Args) -> Result<Option<LoxObj>> {
+    pub fn invoke_user_fn(&mut self, def: &LoxUserFn, args: &[LoxObj]) -> Result<Option<LoxObj>> {
         Self::ensure_arities(def.params.len(), args.len())?;
-        let scope = self.scope_from_args(&def.params, args, &def.closure)?;
-        self.interpret_stmts_with_scope(&def.body, scope)
+        if self.call_depth >= self.max_call_depth {
+            return Err(RuntimeErrorKind::StackOverflow.into());
+        }
+        let scope = self::scope_from_args(&def.params, args, &def.closure)?;
+        self.call_depth += 1;
+        let result = self
+            .interpret_stmts_with_scope(&def.body, scope)
+            .map_err(|why| why.exit_fn(&def.name));
+        self.call_depth -= 1;
+        // `break` and `continue` never go out of a function (the `Resolver` ensures it)
+        Ok(match result? {
+            Some(Flow::Return(obj)) => Some(obj),
+            _ => None,
+        })
     }
 
     fn ensure_arities(n1: usize, n2: usize) -> Result<()> {
         if n1 != n2 {
-            Err(RuntimeError::WrongNumberOfArguments)
+            Err(RuntimeErrorKind::WrongNumberOfArguments.into())
         } else {
             Ok(())
         }
     }
 
-    fn scope_from_args(
-        &mut self,
-        params: &[String],
-        args: &[Expr],
-        closure: &Rc<RefCell<Env>>,
-    ) -> Result<Env> {
-        let mut scope = Env::from_parent(closure);
-        for i in 0..params.len() {
-            scope.define(params[i].as_str(), self.eval_expr(&args[i])?)?;
+    /// The object a `catch` block receives: the object thrown with `throw`, or an instance of
+    /// `Error` with the `message`, `line` and `col` of a built-in error
+    fn catch_obj(&mut self, why: RuntimeError) -> LoxObj {
+        if let RuntimeErrorKind::Thrown(_) = why.kind {
+            if let Some(obj) = self.thrown.take() {
+                return obj;
+            }
         }
-        Ok(scope)
+        let mut error = LoxInstance::new(&self.error_class);
+        error.set("message", why.kind.to_string().into());
+        let (line, col) = match why.pos {
+            Some(pos) => ((pos.ln() as f64).into(), (pos.col() as f64).into()),
+            None => (LoxObj::nil(), LoxObj::nil()),
+        };
+        error.set("line", line);
+        error.set("col", col);
+        self.track(LoxObj::Instance(Rc::new(RefCell::new(error))))
     }
 
     /// Milli seconds since the Lox program is started
-    pub fn native_clock(&self, args: &Args) -> Result<LoxValue> {
-        Self::ensure_arities(0, args.len())?;
-        Ok(LoxValue::Number(
-            self.begin_time.elapsed().unwrap().as_millis() as f64,
-        ))
+    fn native_clock(&self) -> LoxValue {
+        LoxValue::Number(self.begin_time.elapsed().unwrap().as_millis() as f64)
+    }
+}
+
+/// Fails before the Rust stack runs out. Statements and expressions are visited recursively, so
+/// nested blocks take the stack as well as nested calls.
+fn check_stack() -> Result<()> {
+    match stacker::remaining_stack() {
+        Some(remaining) if remaining < STACK_RED_ZONE => {
+            Err(RuntimeErrorKind::StackOverflow.into())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn scope_from_args(params: &[String], args: &[LoxObj], closure: &Rc<RefCell<Env>>) -> Result<Env> {
+    let mut scope = Env::from_parent(closure);
+    for (param, arg) in params.iter().zip(args.iter()) {
+        scope.define(param.as_str(), arg.clone())?;
+    }
+    Ok(scope)
+}
+
+/// Describes a thrown object for the uncaught error: the `message` of an `Error` or the object
+fn describe_thrown(obj: &LoxObj) -> String {
+    if let LoxObj::Instance(instance) = obj {
+        if let Some(LoxObj::Value(LoxValue::StringLit(message))) =
+            instance.borrow().fields().get("message")
+        {
+            return message.clone();
+        }
+    }
+    match obj {
+        LoxObj::Value(LoxValue::StringLit(s)) => s.clone(),
+        _ => obj.pretty_print(),
     }
 }
 
@@ -143,28 +459,28 @@ fn stringify_obj(obj: &LoxObj) -> String {
 
 /// Implements statement interpretation via Visitor pattern
 ///
-/// If something is returned, it's by `return` so we finish interpreting
-impl StmtVisitor<Result<Option<LoxObj>>> for Interpreter {
-    fn visit_expr_stmt(&mut self, expr: &Expr) -> Result<Option<LoxObj>> {
+/// If something is returned, it's by `return`, `break` or `continue` so we stop interpreting
+impl StmtVisitor<Result<Option<Flow>>> for Interpreter {
+    fn visit_expr_stmt(&mut self, expr: &Expr) -> Result<Option<Flow>> {
         let v = self.eval_expr(expr)?;
         Ok(None)
     }
 
-    fn visit_print_stmt(&mut self, print: &PrintArgs) -> Result<Option<LoxObj>> {
+    fn visit_print_stmt(&mut self, print: &PrintArgs) -> Result<Option<Flow>> {
         let obj = self.eval_expr(&print.expr)?;
         // TODO: string should not be quoted
-        println!("{}", obj.pretty_print());
+        writeln!(self.out, "{}", obj.pretty_print()).map_err(RuntimeErrorKind::Output)?;
         Ok(None)
     }
 
-    fn visit_var_decl(&mut self, var: &VarDeclArgs) -> Result<Option<LoxObj>> {
+    fn visit_var_decl(&mut self, var: &VarDeclArgs) -> Result<Option<Flow>> {
         let name = &var.name;
         let obj = self.eval_expr(&var.init)?;
         self.env.borrow_mut().define(name, obj)?;
         Ok(None)
     }
 
-    fn visit_if_stmt(&mut self, if_: &IfArgs) -> Result<Option<LoxObj>> {
+    fn visit_if_stmt(&mut self, if_: &IfArgs) -> Result<Option<Flow>> {
         if self.eval_expr(&if_.condition)?.is_truthy() {
             return self.visit_block_stmt(&if_.if_true.stmts);
         }
@@ -178,50 +494,141 @@ impl StmtVisitor<Result<Option<LoxObj>>> for Interpreter {
         }
     }
 
-    fn visit_block_stmt(&mut self, stmts: &Vec<Stmt>) -> Result<Option<LoxObj>> {
+    fn visit_block_stmt(&mut self, stmts: &Vec<Stmt>) -> Result<Option<Flow>> {
         self.interpret_stmts_with_scope(stmts, Env::from_parent(&self.env))
     }
 
     // TODO: enable returning even outside block
-    fn visit_return_stmt(&mut self, ret: &Return) -> Result<Option<LoxObj>> {
+    fn visit_return_stmt(&mut self, ret: &Return) -> Result<Option<Flow>> {
         let obj = self.eval_expr(&ret.expr)?;
-        Ok(Some(obj))
+        Ok(Some(Flow::Return(obj)))
     }
 
-    fn visit_while_stmt(&mut self, while_: &WhileArgs) -> Result<Option<LoxObj>> {
+    fn visit_while_stmt(&mut self, while_: &WhileArgs) -> Result<Option<Flow>> {
         while self.eval_expr(&while_.condition)?.is_truthy() {
-            // early return considered
-            self.interpret_stmts_with_scope(&while_.block.stmts, Env::from_parent(&self.env))?;
+            let scope = Env::from_parent(&self.env);
+            match self.interpret_stmts_with_scope(&while_.block.stmts, scope)? {
+                Some(Flow::Break) => break,
+                Some(Flow::Continue) | None => {}
+                // early return considered
+                Some(ret @ Flow::Return(_)) => return Ok(Some(ret)),
+            }
+        }
+        Ok(None)
+    }
+
+    fn visit_for_stmt(&mut self, for_: &ForArgs) -> Result<Option<Flow>> {
+        let items: Box<dyn Iterator<Item = LoxObj>> = match for_.iter {
+            ForIter::Range(ref start, ref end) => {
+                let start = self.eval_expr(start)?;
+                let start = start.as_num().ok_or(RuntimeErrorKind::MismatchedType)?;
+                let end = self.eval_expr(end)?;
+                let end = end.as_num().ok_or(RuntimeErrorKind::MismatchedType)?;
+                Box::new(
+                    (0u64..)
+                        .map(move |i| start + i as f64)
+                        .take_while(move |n| *n < end)
+                        .map(|n| LoxObj::Value(LoxValue::Number(n))),
+                )
+            }
+            ForIter::Each(ref iter) => {
+                let items = self.eval_expr(iter)?.items().ok_or_else(|| {
+                    RuntimeError::from(RuntimeErrorKind::NotIterable).or_at(iter.pos())
+                })?;
+                Box::new(items.into_iter())
+            }
+        };
+        for item in items {
+            // an empty body makes no step
+            self.budget.step()?;
+            let mut scope = Env::from_parent(&self.env);
+            scope.define(&for_.var, item)?;
+            match self.interpret_stmts_with_scope(&for_.block.stmts, scope)? {
+                Some(Flow::Break) => break,
+                Some(Flow::Continue) | None => {}
+                Some(ret @ Flow::Return(_)) => return Ok(Some(ret)),
+            }
         }
         Ok(None)
     }
 
-    fn visit_fn_decl(&mut self, def: &FnDeclArgs) -> Result<Option<LoxObj>> {
+    fn visit_break_stmt(&mut self, _pos: Location) -> Result<Option<Flow>> {
+        Ok(Some(Flow::Break))
+    }
+
+    fn visit_continue_stmt(&mut self, _pos: Location) -> Result<Option<Flow>> {
+        Ok(Some(Flow::Continue))
+    }
+
+    fn visit_fn_decl(&mut self, def: &FnDeclArgs) -> Result<Option<Flow>> {
         let f = LoxObj::f(def, &self.env);
         self.env.borrow_mut().define(def.name.as_str(), f)?;
         Ok(None)
     }
 
-    // TODO: do not clone
-    fn visit_class_decl(&mut self, c: &ClassDeclArgs) -> Result<Option<LoxObj>> {
-        let mut methods = HashMap::<String, LoxFn>::new();
-        for f in c.methods.iter() {
-            let method = LoxFn::from_decl(f, &self.env);
-            methods.insert(f.name.to_owned(), method);
-        }
-        let class = LoxClass {
-            name: c.name.to_owned(),
-            methods: methods,
+    fn visit_class_decl(&mut self, c: &ClassDeclArgs) -> Result<Option<Flow>> {
+        let superclass = match c.superclass {
+            Some(ref var) => Some(
+                self.lookup_superclass(var)
+                    .map_err(|why| why.or_at(var.pos))?,
+            ),
+            None => None,
         };
-        self.env
-            .borrow_mut()
-            .define(&c.name, LoxObj::Class(Rc::new(class)))?;
-        // self.env
-        //     .borrow_mut()
-        //     .assign(&c.name, LoxObj::Class(LoxClass::new(
-        //                 )));
+        // methods of a subclass are closed over an `Env` where `super` is defined
+        let closure = match superclass {
+            Some(ref superclass) => {
+                let mut env = Env::from_parent(&self.env);
+                env.define("super", LoxObj::Class(Rc::clone(superclass)))?;
+                self.alloc_env(env)
+            }
+            None => Rc::clone(&self.env),
+        };
+        let class = LoxClass::from_decl(c, superclass, &closure);
+        let class = self.track(LoxObj::Class(Rc::new(class)));
+        self.env.borrow_mut().define(&c.name, class)?;
         Ok(None)
     }
+
+    fn visit_throw_stmt(&mut self, throw: &ThrowArgs) -> Result<Option<Flow>> {
+        let obj = self.eval_expr(&throw.expr)?;
+        let description = self::describe_thrown(&obj);
+        self.thrown = Some(obj);
+        Err(RuntimeErrorKind::Thrown(description).into())
+    }
+
+    fn visit_try_stmt(&mut self, try_: &TryArgs) -> Result<Option<Flow>> {
+        let mut result = self.visit_block_stmt(&try_.body.stmts);
+        if let Some(ref catch) = try_.catch {
+            result = match result {
+                Err(why) if why.kind.is_catchable() => {
+                    let obj = self.catch_obj(why);
+                    let mut scope = Env::from_parent(&self.env);
+                    scope.define(&catch.var, obj)?;
+                    self.interpret_stmts_with_scope(&catch.block.stmts, scope)
+                }
+                result => result,
+            };
+        }
+        let finally = match try_.finally {
+            Some(ref finally) => finally,
+            None => return result,
+        };
+        if let Err(ref why) = result {
+            if !why.kind.is_catchable() {
+                return result;
+            }
+        }
+        // keep the object being thrown (if any) while the `finally` block throws and catches others
+        let thrown = self.thrown.take();
+        match self.visit_block_stmt(&finally.stmts)? {
+            // `return`, `break` or `continue` in the `finally` block discards the result
+            Some(flow) => Ok(Some(flow)),
+            None => {
+                self.thrown = thrown;
+                result
+            }
+        }
+    }
 }
 
 fn runtime_err() {
@@ -235,7 +642,9 @@ pub trait EvalExpr {
 
 impl EvalExpr for Interpreter {
     fn eval_expr(&mut self, expr: &Expr) -> Result<LoxObj> {
-        self.visit_expr(expr)
+        self::check_stack().map_err(|why| why.or_at(expr.pos()))?;
+        self.budget.step().map_err(|why| why.or_at(expr.pos()))?;
+        self.visit_expr(expr).map_err(|why| why.or_at(expr.pos()))
     }
 }
 
@@ -245,9 +654,47 @@ use LoxObj::Value as ValObj;
 mod logic {
     //! Operator overloading for specific LoxObj_s.
 
+    use crate::ast::expr::BinaryOper;
     use crate::runtime::obj::{LoxObj, LoxValue};
+    use crate::runtime::{Result, RuntimeErrorKind};
     use std::cmp::Ordering;
 
+    /// `==`, `!=`, `<`, `<=`, `>`, `>=`, `+`, `-`, `*`, `/`
+    pub fn binary(oper: &BinaryOper, left: &LoxObj, right: &LoxObj) -> Result<LoxObj> {
+        use BinaryOper::*;
+        let left = left.as_value().ok_or(RuntimeErrorKind::MismatchedType)?;
+        let right = right.as_value().ok_or(RuntimeErrorKind::MismatchedType)?;
+
+        // TODO: error if failed to cast
+        Ok(match oper {
+            Equal | NotEqual => {
+                let cp = self::obj_eq(left, right).ok_or(RuntimeErrorKind::MismatchedType)?;
+                LoxObj::bool(cp)
+            }
+
+            Less | LessEqual | Greater | GreaterEqual => {
+                let ord = self::obj_cmp(left, right).ok_or(RuntimeErrorKind::MismatchedType)?;
+                // TODO: no branch
+                LoxObj::bool(match oper {
+                    Less => ord == Ordering::Less,
+                    LessEqual => ord != Ordering::Greater,
+                    Greater => ord == Ordering::Greater,
+                    GreaterEqual => ord != Ordering::Less,
+                    _ => panic!(),
+                })
+            }
+
+            Minus | Plus | Div | Mul => match oper {
+                Minus => self::obj_minus(left, right),
+                Plus => self::obj_plus(left, right),
+                Div => self::obj_div(left, right),
+                Mul => self::obj_mul(left, right),
+                _ => panic!(),
+            }
+            .ok_or(RuntimeErrorKind::MismatchedType)?,
+        })
+    }
+
     pub fn obj_eq(left: &LoxValue, right: &LoxValue) -> Option<bool> {
         Some(match (left, right) {
             (LoxValue::Number(n1), LoxValue::Number(n2)) => n1 == n2,
@@ -301,15 +748,15 @@ mod logic {
 /// Visitors for implementing `eval_expr`
 impl ExprVisitor<Result<LoxObj>> for Interpreter {
     fn visit_literal_expr(&mut self, lit: &LiteralData) -> Result<LoxObj> {
-        Ok(ValObj(LoxValue::from_lit(lit)))
+        Ok(ValObj(LoxValue::from_lit(&lit.value)))
     }
 
     fn visit_unary_expr(&mut self, unary: &UnaryData) -> Result<LoxObj> {
-        let obj = self.visit_expr(&unary.expr)?;
+        let obj = self.eval_expr(&unary.expr)?;
         use UnaryOper::*;
         match &unary.oper {
             Minus => {
-                let n = obj.as_num().ok_or_else(|| RuntimeError::MismatchedType)?;
+                let n = obj.as_num().ok_or(RuntimeErrorKind::MismatchedType)?;
                 Ok(LoxObj::Value(LoxValue::Number(-n)))
             }
             Not => Ok(LoxObj::bool(!obj.is_truthy())),
@@ -318,64 +765,25 @@ impl ExprVisitor<Result<LoxObj>> for Interpreter {
 
     /// `==`, `!=`, `<`, `<=`, `>`, `>=`, `+`, `-`, `*`, `/`
     fn visit_binary_expr(&mut self, binary: &BinaryData) -> Result<LoxObj> {
-        use BinaryOper::*;
-        let oper = binary.oper.clone();
-
-        let left = self.visit_expr(&binary.left)?;
-        let right = self.visit_expr(&binary.right)?;
-
-        let left = left
-            .as_value()
-            .ok_or_else(|| RuntimeError::MismatchedType)?;
-        let right = right
-            .as_value()
-            .ok_or_else(|| RuntimeError::MismatchedType)?;
-
-        // TODO: error if failed to cast
-        Ok(match oper {
-            Equal | NotEqual => {
-                let cp = logic::obj_eq(left, right).ok_or_else(|| RuntimeError::MismatchedType)?;
-                LoxObj::bool(cp)
-            }
-
-            Less | LessEqual | Greater | GreaterEqual => {
-                let ord =
-                    logic::obj_cmp(left, right).ok_or_else(|| RuntimeError::MismatchedType)?;
-                // TODO: no branch
-                LoxObj::bool(match binary.oper {
-                    Less => ord == Ordering::Less,
-                    LessEqual => ord != Ordering::Greater,
-                    Greater => ord == Ordering::Greater,
-                    GreaterEqual => ord != Ordering::Less,
-                    _ => panic!(),
-                })
-            }
-
-            Minus | Plus | Div | Mul => match oper {
-                Minus => logic::obj_minus(left, right),
-                Plus => logic::obj_plus(left, right),
-                Div => logic::obj_div(left, right),
-                Mul => logic::obj_mul(left, right),
-                _ => panic!(),
-            }
-            .ok_or_else(|| RuntimeError::MismatchedType)?,
-        })
+        let left = self.eval_expr(&binary.left)?;
+        let right = self.eval_expr(&binary.right)?;
+        logic::binary(&binary.oper, &left, &right)
     }
 
     /// `&&`, `||`
     fn visit_logic_expr(&mut self, logic: &LogicData) -> Result<LoxObj> {
         let oper = logic.oper.clone();
-        let left_truthy = self.visit_expr(&logic.left)?.is_truthy();
+        let left_truthy = self.eval_expr(&logic.left)?.is_truthy();
         Ok(match oper {
             LogicOper::Or => {
                 if left_truthy {
                     LoxObj::bool(true)
                 } else {
-                    LoxObj::bool(self.visit_expr(&logic.right)?.is_truthy())
+                    LoxObj::bool(self.eval_expr(&logic.right)?.is_truthy())
                 }
             }
             LogicOper::And => {
-                LoxObj::bool(left_truthy && self.visit_expr(&logic.right)?.is_truthy())
+                LoxObj::bool(left_truthy && self.eval_expr(&logic.right)?.is_truthy())
             }
         })
     }
@@ -385,49 +793,115 @@ impl ExprVisitor<Result<LoxObj>> for Interpreter {
     }
 
     fn visit_assign_expr(&mut self, assign: &AssignData) -> Result<LoxObj> {
-        let obj = self.eval_expr(&assign.expr)?;
-        self.env
-            .borrow_mut()
-            .assign(assign.assigned.name.as_str(), obj.clone())?;
+        let obj = match assign.oper.binary() {
+            Some(oper) => {
+                let current = self.lookup_resolved(&assign.assigned)?;
+                let rhs = self.eval_expr(&assign.expr)?;
+                logic::binary(&oper, &current, &rhs)?
+            }
+            None => self.eval_expr(&assign.expr)?,
+        };
+        self.assign_resolved(&assign.assigned, obj.clone())?;
         // TODO: maybe forbid chaning assign expression
         Ok(obj)
     }
 
     fn visit_call_expr(&mut self, call: &CallData) -> Result<LoxObj> {
-        match self.eval_expr(&call.callee)? {
-            LoxObj::Callable(ref fn_obj) => {
-                let obj = self
-                    .invoke(fn_obj, &call.args)?
-                    .unwrap_or_else(|| LoxObj::nil());
-                Ok(obj)
+        let callee = self.eval_expr(&call.callee)?;
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.eval_expr(arg))
+            .collect::<Result<Vec<_>>>()?;
+        self.call(&callee, &args)
+    }
+
+    fn visit_get_expr(&mut self, get: &GetUseData) -> Result<LoxObj> {
+        let body = self.eval_expr(&get.body)?;
+        match body {
+            // methods are bound to a new scope
+            LoxObj::Instance(ref instance) => {
+                let obj = LoxInstance::get(instance, &get.name)?;
+                Ok(self.track(obj))
             }
-            // we treat a class name as a constructor
-            LoxObj::Class(ref class) => {
-                let instance = LoxInstance::new(class);
-                let instance = Rc::new(RefCell::new(instance));
-                // BE CAREFUL NOT TO BORROW TOO LONG!
-                // if let Some(initializer) = instance.borrow().class.find_method("init") {
-                let initializer = instance.borrow().class.find_method("init");
-                if initializer.is_some() {
-                    let initializer = initializer.unwrap();
-                    match initializer {
-                        LoxFn::User(initializer) => {
-                            self.invoke_user_fn(&initializer.bind(&instance)?, &call.args)?;
-                        }
-                        _ => panic!(),
-                    }
-                }
-                Ok(LoxObj::Instance(instance))
+            LoxObj::List(_) | LoxObj::Map(_) => Ok(LoxObj::Callable(LoxFn::Builtin(
+                LoxBuiltinMethod::bind(&body, &get.name)?,
+            ))),
+            _ => Err(RuntimeErrorKind::NotForDotOperator.into()),
+        }
+    }
+
+    fn visit_list_expr(&mut self, list: &ListData) -> Result<LoxObj> {
+        let items = list
+            .items
+            .iter()
+            .map(|item| self.eval_expr(item))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self.track(LoxObj::list(items)))
+    }
+
+    fn visit_map_expr(&mut self, map: &MapData) -> Result<LoxObj> {
+        let mut entries = LoxMap::new();
+        for (key_expr, value) in map.entries.iter() {
+            let key = self.eval_expr(key_expr)?;
+            let key = LoxKey::from_obj(&key).map_err(|why| why.or_at(key_expr.pos()))?;
+            entries.insert(key, self.eval_expr(value)?);
+        }
+        Ok(self.track(LoxObj::map(entries)))
+    }
+
+    fn visit_index_expr(&mut self, index: &IndexData) -> Result<LoxObj> {
+        let body = self.eval_expr(&index.body)?;
+        let i = self.eval_expr(&index.index)?;
+        match body {
+            LoxObj::List(ref list) => {
+                let list = list.borrow();
+                let i = builtin::to_index(&i, list.len())?;
+                Ok(list[i].clone())
             }
-            _ => Err(RuntimeError::MismatchedType),
+            LoxObj::Map(ref map) => builtin::map_get(&map.borrow(), &LoxKey::from_obj(&i)?),
+            _ => Err(RuntimeErrorKind::NotIndexable.into()),
         }
     }
 
-    fn visit_get_expr(&mut self, get: &GetUseData) -> Result<LoxObj> {
-        let body = self.eval_expr(&get.body)?;
+    fn visit_set_index_expr(&mut self, set: &SetIndexData) -> Result<LoxObj> {
+        let body = self.eval_expr(&set.body)?;
+        let i = self.eval_expr(&set.index)?;
+        // the body and the index are evaluated only once even for compound assignments
         match body {
-            LoxObj::Instance(ref instance) => LoxInstance::get(instance, &get.name),
-            _ => Err(RuntimeError::NotForDotOperator),
+            LoxObj::List(list) => {
+                let i = builtin::to_index(&i, list.borrow().len())?;
+                let obj = match set.oper.binary() {
+                    Some(oper) => {
+                        let current = list.borrow()[i].clone();
+                        let rhs = self.eval_expr(&set.value)?;
+                        logic::binary(&oper, &current, &rhs)?
+                    }
+                    None => self.eval_expr(&set.value)?,
+                };
+                // the list can be shrinked while evaluating the value
+                let mut list = list.borrow_mut();
+                let len = list.len();
+                let item = list
+                    .get_mut(i)
+                    .ok_or(RuntimeErrorKind::IndexOutOfBounds(i, len))?;
+                *item = obj.clone();
+                Ok(obj)
+            }
+            LoxObj::Map(map) => {
+                let key = LoxKey::from_obj(&i)?;
+                let obj = match set.oper.binary() {
+                    Some(oper) => {
+                        let current = builtin::map_get(&map.borrow(), &key)?;
+                        let rhs = self.eval_expr(&set.value)?;
+                        logic::binary(&oper, &current, &rhs)?
+                    }
+                    None => self.eval_expr(&set.value)?,
+                };
+                map.borrow_mut().insert(key, obj.clone());
+                Ok(obj)
+            }
+            _ => Err(RuntimeErrorKind::NotIndexable.into()),
         }
     }
 
@@ -436,17 +910,290 @@ impl ExprVisitor<Result<LoxObj>> for Interpreter {
         let body = self.eval_expr(&set.body)?;
         match body {
             LoxObj::Instance(instance) => {
-                let obj = self.eval_expr(&set.value)?;
-                instance.borrow_mut();
+                // the body is evaluated only once even for compound assignments
+                let obj = match set.oper.binary() {
+                    Some(oper) => {
+                        let current = LoxInstance::get(&instance, &set.name)?;
+                        let rhs = self.eval_expr(&set.value)?;
+                        logic::binary(&oper, &current, &rhs)?
+                    }
+                    None => self.eval_expr(&set.value)?,
+                };
                 instance.borrow_mut().set(&set.name, obj);
                 // TODO: is it ok to return nil
                 Ok(LoxObj::nil())
             }
-            _ => Err(RuntimeError::NotForDotOperator),
+            _ => Err(RuntimeErrorKind::NotForDotOperator.into()),
         }
     }
 
     fn visit_self_expr(&mut self, self_: &SelfData) -> Result<LoxObj> {
-        self.env.borrow().get_resolved("@", 0)
+        self.lookup_resolved(&self_.var)
+    }
+
+    /// Binds a method of the superclass to `@`
+    fn visit_super_expr(&mut self, super_: &SuperData) -> Result<LoxObj> {
+        let slot = super_
+            .var
+            .slot()
+            .ok_or_else(|| RuntimeErrorKind::Undefined("super".to_string()))?;
+        let superclass = match self.env.borrow().get_resolved(slot) {
+            LoxObj::Class(class) => class,
+            _ => return Err(RuntimeErrorKind::MismatchedType.into()),
+        };
+        // `@` is defined in the scope just inside the one with `super`
+        let self_slot = LocalSlot {
+            depth: slot.depth - 1,
+            index: 0,
+        };
+        let instance = match self.env.borrow().get_resolved(self_slot) {
+            LoxObj::Instance(instance) => instance,
+            _ => return Err(RuntimeErrorKind::MismatchedType.into()),
+        };
+        let method = superclass
+            .find_method(&super_.method)
+            .ok_or_else(|| RuntimeErrorKind::NoFieldWithName(super_.method.to_owned()))?;
+        let method = LoxObj::Callable(method.bind(&instance)?);
+        Ok(self.track(method))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Interpreter;
+    use crate::cli::{self, RunContext};
+    use crate::embed::{Lox, LoxError};
+    use crate::runtime::{
+        obj::{LoxFn, LoxObj, LoxValue},
+        RuntimeErrorKind,
+    };
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::atomic::Ordering;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn define_native() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .define_native("add", 2, |_interpreter, args| {
+                match (args[0].as_num(), args[1].as_num()) {
+                    (Some(a), Some(b)) => Ok(LoxValue::Number(a + b).into()),
+                    _ => Err(RuntimeErrorKind::Native("expected numbers".into()).into()),
+                }
+            })
+            .unwrap();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let log_ = Rc::clone(&log);
+        interpreter
+            .define_native("record", 1, move |_interpreter, args| {
+                log_.borrow_mut().push(args[0].as_num().unwrap());
+                Ok(LoxObj::nil())
+            })
+            .unwrap();
+
+        let cx = RunContext::default();
+        cli::run_string("<test>", "record(add(1, 2));", &cx, &mut interpreter).unwrap();
+        assert_eq!(*log.borrow(), vec![3.0]);
+
+        // wrong number of arguments or an error from the native function
+        assert!(cli::run_string("<test>", "add(1);", &cx, &mut interpreter).is_err());
+        assert!(cli::run_string("<test>", "add(1, nil);", &cx, &mut interpreter).is_err());
+    }
+
+    #[test]
+    fn free_cyclic_scopes_on_drop() {
+        let mut lox = Lox::new();
+        let src = "fn outer() { fn rec(n) { if n > 0 { rec(n - 1); } } return rec; } outer();";
+        let closure = match lox.eval(src).unwrap() {
+            LoxObj::Callable(LoxFn::User(f)) => Rc::downgrade(&f.closure),
+            obj => panic!("expected a function: {:?}", obj),
+        };
+        // `rec` is stored in the scope it captures
+        assert!(closure.upgrade().is_some());
+        drop(lox);
+        assert!(closure.upgrade().is_none());
+    }
+
+    #[test]
+    fn collect_cycles() {
+        let mut lox = Lox::new();
+        let src = "class Node { fn init() { @.me = @; @.f = @.get; } fn get() { return 1; } }
+        var xs = [];
+        fn cycles() {
+            var node = Node();
+            var ys = [];
+            ys.push(ys);
+            xs.push(0);
+        }
+        cycles();";
+        lox.eval(src).unwrap();
+        let n = match lox.eval("gc();").unwrap() {
+            LoxObj::Value(LoxValue::Number(n)) => n,
+            obj => panic!("expected a number: {:?}", obj),
+        };
+        // the instance, its bound method scope and the list
+        assert_eq!(n, 3.0);
+        // reachable objects are kept
+        assert_eq!(lox.eval("xs.len();").unwrap().as_num(), Some(1.0));
+
+        let stats = lox.interpreter().gc_stats();
+        assert_eq!(stats.collected, 3);
+        assert!(stats.collections >= 1);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let mut lox = Lox::new();
+        let src = "class A { fn f(n) { if n > 0 { { return [@.f(n - 1)]; } } return n; } }
+        fn g(n) { return A().f(n); }";
+        lox.eval(src).unwrap();
+        let is_overflow = |result| match result {
+            Err(LoxError::Runtime(why)) => matches!(why.kind, RuntimeErrorKind::StackOverflow),
+            result => panic!("expected a stack overflow: {:?}", result),
+        };
+        // fails before overflowing the 2 MiB stack of a test thread
+        assert!(is_overflow(lox.eval("g(100000);")));
+        // the interpreter is still usable
+        assert!(lox.eval("g(10);").is_ok());
+
+        lox.interpreter().set_max_call_depth(8);
+        assert!(lox.eval("g(6);").is_ok());
+        assert!(is_overflow(lox.eval("g(7);")));
+
+        // nested statements take the stack without calls
+        lox.interpreter().set_max_call_depth(usize::MAX);
+        let nested = "if true { ".repeat(10) + "g(100000);" + &" }".repeat(10);
+        assert!(is_overflow(lox.eval(&nested)));
+    }
+
+    #[test]
+    fn default_call_depth() {
+        let mut lox = Lox::new();
+        let src = "fn depth(n) { if n == 0 { return 0; } return 1 + depth(n - 1); }";
+        lox.eval(src).unwrap();
+        assert!(lox.eval("depth(100);").is_ok());
+    }
+
+    #[test]
+    fn budget() {
+        let mut lox = Lox::new();
+        lox.interpreter().set_fuel(Some(100));
+        let kind = |result| match result {
+            Err(LoxError::Runtime(why)) => why.kind,
+            result => panic!("expected a runtime error: {:?}", result),
+        };
+        assert!(matches!(
+            kind(lox.eval("while true {}")),
+            RuntimeErrorKind::OutOfFuel
+        ));
+        assert_eq!(lox.interpreter().fuel(), Some(0));
+        lox.interpreter().set_fuel(None);
+
+        lox.interpreter()
+            .set_deadline(Some(Instant::now() + Duration::from_millis(10)));
+        assert!(matches!(
+            kind(lox.eval("while true {}")),
+            RuntimeErrorKind::DeadlineExceeded
+        ));
+        lox.interpreter().set_deadline(None);
+
+        let interrupt = lox.interpreter().interrupt_handle();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            interrupt.store(true, Ordering::Relaxed);
+        });
+        assert!(matches!(
+            kind(lox.eval("while true {}")),
+            RuntimeErrorKind::Interrupted
+        ));
+        handle.join().unwrap();
+        assert!(lox.eval("1 + 2;").is_ok());
+    }
+
+    #[test]
+    fn budget_for_loops() {
+        let mut lox = Lox::new();
+        let kind = |result| match result {
+            Err(LoxError::Runtime(why)) => why.kind,
+            result => panic!("expected a runtime error: {:?}", result),
+        };
+        let src = "for i in 0..1000000000000000 {}";
+
+        lox.interpreter().set_fuel(Some(100));
+        assert!(matches!(kind(lox.eval(src)), RuntimeErrorKind::OutOfFuel));
+        lox.interpreter().set_fuel(None);
+
+        lox.interpreter()
+            .set_deadline(Some(Instant::now() + Duration::from_millis(10)));
+        assert!(matches!(
+            kind(lox.eval(src)),
+            RuntimeErrorKind::DeadlineExceeded
+        ));
+        lox.interpreter().set_deadline(None);
+
+        let interrupt = lox.interpreter().interrupt_handle();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(10));
+            interrupt.store(true, Ordering::Relaxed);
+        });
+        assert!(matches!(kind(lox.eval(src)), RuntimeErrorKind::Interrupted));
+        handle.join().unwrap();
+
+        // iterating a list steps too
+        lox.eval("var xs = []; while xs.len() < 10 { xs.push(0); }")
+            .unwrap();
+        lox.interpreter().set_fuel(Some(5));
+        assert!(matches!(
+            kind(lox.eval("for x in xs {}")),
+            RuntimeErrorKind::OutOfFuel
+        ));
+    }
+
+    #[test]
+    fn list_positions() {
+        let mut lox = Lox::new();
+        lox.eval("var xs = [1, 2]; xs.insert(2, 3);").unwrap();
+        assert_eq!(
+            lox.eval("xs.slice(1, 3).len();").unwrap().as_num(),
+            Some(2.0)
+        );
+        for src in &["xs.insert(4, 0);", "xs.slice(0, 4);"] {
+            match lox.eval(src) {
+                Err(LoxError::Runtime(why)) => {
+                    assert!(matches!(why.kind, RuntimeErrorKind::IndexOutOfBounds(4, 3)))
+                }
+                result => panic!("expected a runtime error: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn throw_cyclic_list() {
+        let mut lox = Lox::new();
+        match lox.eval("var a = [1]; a.push(a); throw a;") {
+            Err(LoxError::Runtime(why)) => assert!(matches!(
+                why.kind,
+                RuntimeErrorKind::Thrown(ref s) if s == "[1, [...]]"
+            )),
+            result => panic!("expected a runtime error: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn budget_errors_are_not_caught() {
+        let mut lox = Lox::new();
+        lox.interpreter().set_fuel(Some(100));
+        let src = "var caught = false; try { while true {} } catch (e) { caught = true; }";
+        match lox.eval(src) {
+            Err(LoxError::Runtime(why)) => assert!(matches!(why.kind, RuntimeErrorKind::OutOfFuel)),
+            result => panic!("expected a runtime error: {:?}", result),
+        }
+        lox.interpreter().set_fuel(None);
+        assert_eq!(
+            lox.eval("caught;").unwrap().as_value(),
+            Some(&LoxValue::Bool(false))
+        );
     }
 }
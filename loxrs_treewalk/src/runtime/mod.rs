@@ -1,16 +1,108 @@
+pub mod budget;
+pub mod builtin;
 pub mod env;
+pub mod gc;
 pub mod obj;
+pub mod output;
 
 mod interpreter;
-pub use interpreter::Interpreter;
+pub use interpreter::{on_script_stack, Interpreter, DEFAULT_MAX_CALL_DEPTH, SCRIPT_STACK_SIZE};
 
 pub type Result<T> = ::std::result::Result<T, RuntimeError>;
+use crate::lexer::token::Location;
 use thiserror::Error;
 
-// These errors are horribly.. They don't know source positions and they are lack of context!
 /// Error when evaluating expressions.
+///
+/// The source position is filled by the `Interpreter` while the error goes up through the AST.
+/// The backtrace is also made on the way, one frame per function the error goes out of.
 #[derive(Debug, Error)]
-pub enum RuntimeError {
+#[error("{kind}")]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub pos: Option<Location>,
+    /// Boxed to keep `Result`s small, because they're on the stack of every recursive call
+    trace: Option<Box<Trace>>,
+}
+
+#[derive(Debug)]
+struct Trace {
+    /// Functions the error went out of, innermost first
+    frames: Vec<Frame>,
+    /// Position in the function (or script) the error is going through
+    frame_pos: Option<Location>,
+}
+
+/// A function call in a backtrace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub name: String,
+    /// Where the function was running
+    pub pos: Option<Location>,
+}
+
+impl RuntimeError {
+    /// Locates the error if it's not located yet, so that the innermost node is reported
+    pub fn or_at(mut self, pos: Location) -> Self {
+        if self.pos.is_none() {
+            self.pos = Some(pos);
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            if trace.frame_pos.is_none() {
+                trace.frame_pos = Some(pos);
+            }
+        }
+        self
+    }
+
+    /// Records that the error went out of a function
+    pub fn exit_fn(mut self, name: &str) -> Self {
+        // the innermost function is where the error is located
+        let pos = self.pos;
+        let trace = self.trace.get_or_insert_with(|| {
+            Box::new(Trace {
+                frames: Vec::new(),
+                frame_pos: pos,
+            })
+        });
+        trace.frames.push(Frame {
+            name: name.to_owned(),
+            pos: trace.frame_pos.take(),
+        });
+        self
+    }
+
+    /// Function calls the error went through, innermost first and ending with the top-level
+    /// script (if it's called from a script). Empty if the error happened at top level.
+    pub fn backtrace(&self) -> Vec<Frame> {
+        let trace = match self.trace {
+            Some(ref trace) => trace,
+            None => return Vec::new(),
+        };
+        let mut frames = trace.frames.clone();
+        if let Some(pos) = trace.frame_pos {
+            frames.push(Frame {
+                name: "script".to_owned(),
+                pos: Some(pos),
+            });
+        }
+        frames
+    }
+}
+
+impl From<RuntimeErrorKind> for RuntimeError {
+    fn from(kind: RuntimeErrorKind) -> Self {
+        Self {
+            kind: kind,
+            pos: None,
+            trace: None,
+        }
+    }
+}
+
+// These errors are lack of context!
+#[derive(Debug, Error)]
+pub enum RuntimeErrorKind {
     #[error("mismatched type")]
     MismatchedType,
     /// Tried to lookup undefined variable
@@ -28,4 +120,64 @@ pub enum RuntimeError {
     ReassignDisabled,
     #[error("cannot bind")]
     CantBind,
+    #[error("not iterable")]
+    NotIterable,
+    #[error("not indexable")]
+    NotIndexable,
+    #[error("negative index: {0}")]
+    NegativeIndex(f64),
+    #[error("non-integer index: {0}")]
+    NonIntegerIndex(f64),
+    #[error("index out of bounds: the index is {0} but the length is {1}")]
+    IndexOutOfBounds(usize, usize),
+    #[error("invalid slice: {0}..{1}")]
+    InvalidSlice(usize, usize),
+    #[error("no entry found for key {0}")]
+    NoEntryForKey(String),
+    #[error("invalid key (only nil, booleans, strings and numbers except NaN can be keys)")]
+    InvalidKey,
+    #[error("pop from empty list")]
+    PopFromEmptyList,
+    #[error("no method found with name \"{0}\"")]
+    NoMethodWithName(String),
+    #[error("superclass \"{0}\" is not a class")]
+    SuperclassNotClass(String),
+    /// `init` of the class is not a user-defined function
+    #[error("initializer of class \"{0}\" is not a user-defined function")]
+    InvalidInitializer(String),
+    #[error("failed to write output: {0}")]
+    Output(std::io::Error),
+    /// Too deep recursion: exceeded the limit set with `Interpreter::set_max_call_depth` or ran
+    /// low on the Rust stack
+    #[error("stack overflow")]
+    StackOverflow,
+    /// Ran out of the steps given with `Interpreter::set_fuel`
+    #[error("execution budget exhausted")]
+    OutOfFuel,
+    /// Passed the time given with `Interpreter::set_deadline`
+    #[error("deadline exceeded")]
+    DeadlineExceeded,
+    /// Stopped via `Interpreter::interrupt_handle`
+    #[error("interrupted")]
+    Interrupted,
+    /// Error reported by a function defined with `Interpreter::define_native`
+    #[error("{0}")]
+    Native(String),
+    /// An object thrown with `throw` and not caught. The `Interpreter` keeps the object while the
+    /// error has a description of it (errors are sent across threads, but objects can't be).
+    #[error("uncaught exception: {0}")]
+    Thrown(String),
+}
+
+impl RuntimeErrorKind {
+    /// Errors stopping a script for the host can't be caught with `try`, and `finally` blocks
+    /// don't run for them
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            RuntimeErrorKind::OutOfFuel
+                | RuntimeErrorKind::DeadlineExceeded
+                | RuntimeErrorKind::Interrupted
+        )
+    }
 }
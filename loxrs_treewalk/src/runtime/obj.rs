@@ -5,10 +5,13 @@ use crate::ast::{
     pretty_printer::{self, PrettyPrint},
     stmt::{ClassDeclArgs, FnDeclArgs, Params, Stmt},
 };
-use crate::runtime::{env::Env, Result, RuntimeError};
+use crate::runtime::{
+    builtin::LoxBuiltinMethod, env::Env, Interpreter, Result, RuntimeError, RuntimeErrorKind,
+};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use std::fmt::Write;
+use std::convert::TryFrom;
+use std::fmt::{self, Write};
 use std::rc::Rc;
 
 /// Runtime object which represents anything
@@ -19,6 +22,10 @@ pub enum LoxObj {
     // TODO: consider using Rc or not (to reference from instance)
     Class(Rc<LoxClass>),
     Instance(Rc<RefCell<LoxInstance>>),
+    /// Mutable list shared by reference
+    List(Rc<RefCell<Vec<LoxObj>>>),
+    /// Mutable map shared by reference
+    Map(Rc<RefCell<LoxMap>>),
 }
 
 impl LoxObj {
@@ -26,6 +33,14 @@ impl LoxObj {
         LoxObj::Value(LoxValue::Nil)
     }
 
+    pub fn list(items: Vec<LoxObj>) -> Self {
+        LoxObj::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn map(map: LoxMap) -> Self {
+        LoxObj::Map(Rc::new(RefCell::new(map)))
+    }
+
     pub fn f(def: &FnDeclArgs, closure: &Rc<RefCell<Env>>) -> Self {
         LoxObj::Callable(LoxFn::User(LoxUserFn::from_def(def, closure)))
     }
@@ -42,14 +57,132 @@ pub enum LoxValue {
 }
 
 impl LoxValue {
-    pub fn from_lit(lit: &LiteralData) -> Self {
+    pub fn from_lit(lit: &LiteralValue) -> Self {
         match lit {
-            LiteralData::Nil => LoxValue::Nil,
-            LiteralData::Bool(b) => LoxValue::Bool(b.clone()),
-            LiteralData::StringLit(s) => LoxValue::StringLit(s.clone()),
-            LiteralData::Number(n) => LoxValue::Number(n.clone()),
+            LiteralValue::Nil => LoxValue::Nil,
+            LiteralValue::Bool(b) => LoxValue::Bool(b.clone()),
+            LiteralValue::StringLit(s) => LoxValue::StringLit(s.clone()),
+            LiteralValue::Number(n) => LoxValue::Number(n.clone()),
+        }
+    }
+}
+
+/// Hashable `LoxValue` for the keys of maps
+///
+/// Numbers are compared by bits except that `-0` is the same as `0`, and NaN can't be a key.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LoxKey {
+    Nil,
+    Bool(bool),
+    StringLit(String),
+    Number(u64),
+}
+
+impl LoxKey {
+    pub fn from_value(value: &LoxValue) -> Option<Self> {
+        Some(match value {
+            LoxValue::Nil => LoxKey::Nil,
+            LoxValue::Bool(b) => LoxKey::Bool(*b),
+            LoxValue::StringLit(s) => LoxKey::StringLit(s.clone()),
+            LoxValue::Number(n) if n.is_nan() => return None,
+            LoxValue::Number(n) if *n == 0.0 => LoxKey::Number(0.0f64.to_bits()),
+            LoxValue::Number(n) => LoxKey::Number(n.to_bits()),
+        })
+    }
+
+    pub fn from_obj(obj: &LoxObj) -> Result<Self> {
+        obj.as_value()
+            .and_then(Self::from_value)
+            .ok_or_else(|| RuntimeErrorKind::InvalidKey.into())
+    }
+
+    pub fn to_value(&self) -> LoxValue {
+        match self {
+            LoxKey::Nil => LoxValue::Nil,
+            LoxKey::Bool(b) => LoxValue::Bool(*b),
+            LoxKey::StringLit(s) => LoxValue::StringLit(s.clone()),
+            LoxKey::Number(bits) => LoxValue::Number(f64::from_bits(*bits)),
+        }
+    }
+}
+
+/// Map that remembers the insertion order
+///
+/// Removed entries are left as holes in the insertion order so that removing is O(1). The holes
+/// are compacted once they outnumber the entries.
+#[derive(Clone, Debug, Default)]
+pub struct LoxMap {
+    indices: HashMap<LoxKey, usize>,
+    entries: Vec<Option<(LoxKey, LoxObj)>>,
+}
+
+impl LoxMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn contains_key(&self, key: &LoxKey) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    pub fn get(&self, key: &LoxKey) -> Option<&LoxObj> {
+        self.indices
+            .get(key)
+            .and_then(|&i| self.entries[i].as_ref())
+            .map(|(_, value)| value)
+    }
+
+    /// Overwrites the value if the key is already in the map
+    pub fn insert(&mut self, key: LoxKey, value: LoxObj) {
+        match self.indices.get(&key) {
+            Some(&i) => self.entries[i] = Some((key, value)),
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push(Some((key, value)));
+            }
         }
     }
+
+    pub fn remove(&mut self, key: &LoxKey) -> Option<LoxObj> {
+        let i = self.indices.remove(key)?;
+        let (_, value) = self.entries[i].take()?;
+        if self.entries.len() > 2 * self.indices.len() {
+            self.compact();
+        }
+        Some(value)
+    }
+
+    /// Removes the holes left by `remove`
+    fn compact(&mut self) {
+        self.entries.retain(Option::is_some);
+        for (i, (key, _)) in self.entries.iter().flatten().enumerate() {
+            *self.indices.get_mut(key).unwrap() = i;
+        }
+    }
+
+    /// Entries in the insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&LoxKey, &LoxObj)> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &LoxKey> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &LoxObj> {
+        self.iter().map(|(_, value)| value)
+    }
 }
 
 impl From<LoxValue> for LoxObj {
@@ -58,12 +191,90 @@ impl From<LoxValue> for LoxObj {
     }
 }
 
+// conversions from Rust values
+
+impl From<f64> for LoxObj {
+    fn from(n: f64) -> Self {
+        LoxObj::Value(LoxValue::Number(n))
+    }
+}
+
+impl From<bool> for LoxObj {
+    fn from(b: bool) -> Self {
+        LoxObj::Value(LoxValue::Bool(b))
+    }
+}
+
+impl From<&str> for LoxObj {
+    fn from(s: &str) -> Self {
+        LoxObj::Value(LoxValue::StringLit(s.to_owned()))
+    }
+}
+
+impl From<String> for LoxObj {
+    fn from(s: String) -> Self {
+        LoxObj::Value(LoxValue::StringLit(s))
+    }
+}
+
+impl From<Vec<LoxObj>> for LoxObj {
+    fn from(items: Vec<LoxObj>) -> Self {
+        LoxObj::list(items)
+    }
+}
+
+// conversions into Rust values (fails with `MismatchedType`)
+
+impl TryFrom<LoxObj> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(obj: LoxObj) -> Result<Self> {
+        match obj {
+            LoxObj::Value(LoxValue::Number(n)) => Ok(n),
+            _ => Err(RuntimeErrorKind::MismatchedType.into()),
+        }
+    }
+}
+
+impl TryFrom<LoxObj> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(obj: LoxObj) -> Result<Self> {
+        match obj {
+            LoxObj::Value(LoxValue::Bool(b)) => Ok(b),
+            _ => Err(RuntimeErrorKind::MismatchedType.into()),
+        }
+    }
+}
+
+impl TryFrom<LoxObj> for String {
+    type Error = RuntimeError;
+
+    fn try_from(obj: LoxObj) -> Result<Self> {
+        match obj {
+            LoxObj::Value(LoxValue::StringLit(s)) => Ok(s),
+            _ => Err(RuntimeErrorKind::MismatchedType.into()),
+        }
+    }
+}
+
+impl TryFrom<LoxObj> for Vec<LoxObj> {
+    type Error = RuntimeError;
+
+    fn try_from(obj: LoxObj) -> Result<Self> {
+        match obj {
+            LoxObj::List(list) => Ok(list.borrow().clone()),
+            _ => Err(RuntimeErrorKind::MismatchedType.into()),
+        }
+    }
+}
+
 impl LoxObj {
     pub fn bool(b: bool) -> Self {
         LoxObj::Value(LoxValue::Bool(b))
     }
 
-    pub fn from_lit(lit: &LiteralData) -> Self {
+    pub fn from_lit(lit: &LiteralValue) -> Self {
         LoxObj::Value(LoxValue::from_lit(lit))
     }
 
@@ -93,6 +304,25 @@ impl LoxObj {
         }
     }
 
+    /// Items to iterate over with `for`
+    pub fn items(&self) -> Option<Vec<LoxObj>> {
+        match self {
+            LoxObj::Value(LoxValue::StringLit(s)) => Some(
+                s.chars()
+                    .map(|c| LoxObj::Value(LoxValue::StringLit(c.to_string())))
+                    .collect(),
+            ),
+            LoxObj::List(list) => Some(list.borrow().clone()),
+            LoxObj::Map(map) => Some(
+                map.borrow()
+                    .keys()
+                    .map(|key| LoxObj::Value(key.to_value()))
+                    .collect(),
+            ),
+            _ => None,
+        }
+    }
+
     pub fn is_nil(&self) -> bool {
         match self {
             LoxObj::Value(LoxValue::Nil) => true,
@@ -101,7 +331,6 @@ impl LoxObj {
     }
 }
 
-// TODO: remove native functions
 /// Runtime function object (expect class names as constructors)
 ///
 /// It's not so expensive to copy a `LoxFn`
@@ -109,10 +338,10 @@ impl LoxObj {
 pub enum LoxFn {
     /// User defined function
     User(LoxUserFn),
-    /// A native function embedded in rulox
-    Clock,
-    // /// Generic native function identifier
-    // Native(String, Option<Args>),
+    /// A function defined by the host in Rust
+    Native(LoxNativeFn),
+    /// A method of a built-in type such as list
+    Builtin(LoxBuiltinMethod),
 }
 
 impl LoxFn {
@@ -123,11 +352,31 @@ impl LoxFn {
     pub fn bind(&self, instance: &Rc<RefCell<LoxInstance>>) -> Result<Self> {
         match self {
             LoxFn::User(f) => Ok(LoxFn::User(f.bind(instance)?)),
-            _ => Err(RuntimeError::CantBind),
+            _ => Err(RuntimeErrorKind::CantBind.into()),
         }
     }
 }
 
+/// Body of a native function, which takes evaluated arguments
+pub type NativeFnBody = dyn Fn(&mut Interpreter, &[LoxObj]) -> Result<LoxObj>;
+
+/// Function registered with `Interpreter::define_native`
+#[derive(Clone)]
+pub struct LoxNativeFn {
+    pub name: String,
+    pub arity: usize,
+    pub body: Rc<NativeFnBody>,
+}
+
+impl fmt::Debug for LoxNativeFn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LoxNativeFn")
+            .field("name", &self.name)
+            .field("arity", &self.arity)
+            .finish()
+    }
+}
+
 /// Runtime representaiton of a user-defined function.
 #[derive(Clone, Debug)]
 pub struct LoxUserFn {
@@ -136,7 +385,8 @@ pub struct LoxUserFn {
     pub params: Params,
     // TODO: disable mutation
     pub closure: Rc<RefCell<Env>>,
-    // TODO: should have name in field or not
+    /// The name shown in backtraces (`Class.method` for methods)
+    pub name: String,
 }
 
 impl LoxUserFn {
@@ -145,6 +395,7 @@ impl LoxUserFn {
             body: Rc::clone(&decl.body),
             params: decl.params.clone(),
             closure: Rc::clone(closure),
+            name: decl.name.clone(),
         }
     }
 
@@ -155,6 +406,7 @@ impl LoxUserFn {
             body: Rc::clone(&self.body),
             params: self.params.clone(),
             closure: Rc::new(RefCell::new(env)),
+            name: self.name.clone(),
         })
     }
 }
@@ -163,23 +415,39 @@ impl LoxUserFn {
 #[derive(Clone, Debug)]
 pub struct LoxClass {
     pub name: String,
+    pub superclass: Option<Rc<LoxClass>>,
     pub methods: HashMap<String, LoxFn>,
 }
 
 impl LoxClass {
-    pub fn from_decl(decl: &ClassDeclArgs, closure: &Rc<RefCell<Env>>) -> Self {
+    pub fn from_decl(
+        decl: &ClassDeclArgs,
+        superclass: Option<Rc<LoxClass>>,
+        closure: &Rc<RefCell<Env>>,
+    ) -> Self {
         Self {
             name: decl.name.clone(),
+            superclass: superclass,
             methods: decl
                 .methods
                 .iter()
-                .map(|m| (m.name.to_owned(), LoxFn::from_decl(m, closure)))
+                .map(|m| {
+                    let method = LoxUserFn {
+                        name: format!("{}.{}", decl.name, m.name),
+                        ..LoxUserFn::from_def(m, closure)
+                    };
+                    (m.name.to_owned(), LoxFn::User(method))
+                })
                 .collect(),
         }
     }
 
+    /// Looks up this class and then superclasses
     pub fn find_method(&self, name: &str) -> Option<LoxFn> {
-        self.methods.get(name).map(|m| m.clone())
+        match self.methods.get(name) {
+            Some(m) => Some(m.clone()),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
     }
 }
 
@@ -214,7 +482,7 @@ impl LoxInstance {
             let binded = method.bind(self_)?;
             Ok(LoxObj::Callable(binded))
         } else {
-            Err(RuntimeError::NoFieldWithName(name.to_string()))
+            Err(RuntimeErrorKind::NoFieldWithName(name.to_string()).into())
         }
     }
 
@@ -222,9 +490,18 @@ impl LoxInstance {
         self.fields.insert(name.to_owned(), value);
     }
 
+    pub fn fields(&self) -> &HashMap<String, LoxObj> {
+        &self.fields
+    }
+
+    /// Removes all the fields, which breaks reference cycles through this instance
+    pub fn take_fields(&mut self) -> HashMap<String, LoxObj> {
+        std::mem::take(&mut self.fields)
+    }
+
     pub fn try_assign(&mut self, name: &str, value: LoxObj) -> Result<AssignHandle> {
         if let Some(obj) = self.fields.get_mut(name) {
-            Err(RuntimeError::ReassignDisabled)
+            Err(RuntimeErrorKind::ReassignDisabled.into())
         } else {
             // FIXME: reduce cloning
             Ok(AssignHandle {
@@ -238,7 +515,7 @@ impl LoxInstance {
             *obj = value;
             Ok(())
         } else {
-            Err(RuntimeError::NoFieldWithName(name.to_owned()))
+            Err(RuntimeErrorKind::NoFieldWithName(name.to_owned()).into())
         }
     }
 }
@@ -264,12 +541,56 @@ impl PrettyPrint for LoxValue {
 
 impl PrettyPrint for LoxObj {
     fn pretty_print(&self) -> String {
-        match self {
-            LoxObj::Value(value) => value.pretty_print(),
-            LoxObj::Callable(call) => call.pretty_print(),
-            LoxObj::Class(class) => class.pretty_print(),
-            // TODO: test if it will get panic
-            LoxObj::Instance(instance) => instance.borrow().pretty_print(),
+        let mut s = String::new();
+        self::write_obj(&mut s, self, &mut Vec::new());
+        s
+    }
+}
+
+/// Writes an object. `printing` has the lists and maps being written, so that a list or a map
+/// containing itself is written as `[...]` or `{...}` inside instead of recursing forever.
+fn write_obj(s: &mut String, obj: &LoxObj, printing: &mut Vec<*const ()>) {
+    match obj {
+        LoxObj::Value(value) => s.push_str(&value.pretty_print()),
+        LoxObj::Callable(call) => s.push_str(&call.pretty_print()),
+        LoxObj::Class(class) => s.push_str(&class.pretty_print()),
+        // TODO: test if it will get panic
+        LoxObj::Instance(instance) => self::write_instance(s, &instance.borrow(), printing),
+        LoxObj::List(list) => {
+            let ptr = Rc::as_ptr(list) as *const ();
+            if printing.contains(&ptr) {
+                s.push_str("[...]");
+                return;
+            }
+            printing.push(ptr);
+            s.push('[');
+            for (i, item) in list.borrow().iter().enumerate() {
+                if i > 0 {
+                    s.push_str(", ");
+                }
+                self::write_obj(s, item, printing);
+            }
+            s.push(']');
+            printing.pop();
+        }
+        LoxObj::Map(map) => {
+            let ptr = Rc::as_ptr(map) as *const ();
+            if printing.contains(&ptr) {
+                s.push_str("{...}");
+                return;
+            }
+            printing.push(ptr);
+            let entries = map
+                .borrow()
+                .iter()
+                .map(|(key, value)| {
+                    let mut entry = format!("{}: ", key.to_value().pretty_print());
+                    self::write_obj(&mut entry, value, printing);
+                    entry
+                })
+                .collect::<Vec<_>>();
+            write!(s, "{{{}}}", entries.join(", ")).unwrap();
+            printing.pop();
         }
     }
 }
@@ -277,7 +598,8 @@ impl PrettyPrint for LoxObj {
 impl PrettyPrint for LoxFn {
     fn pretty_print(&self) -> String {
         match self {
-            LoxFn::Clock => "(fn clock)".into(),
+            LoxFn::Native(ref native) => format!("(fn {})", native.name),
+            LoxFn::Builtin(ref method) => format!("(fn {})", method.name),
             LoxFn::User(ref user) => user.pretty_print(),
         }
     }
@@ -306,20 +628,27 @@ impl PrettyPrint for LoxClass {
 impl PrettyPrint for LoxInstance {
     fn pretty_print(&self) -> String {
         let mut s = String::new();
-        self::write_instance(&mut s, self);
+        self::write_instance(&mut s, self, &mut Vec::new());
         s
     }
 }
 
-fn write_instance(s: &mut String, instance: &LoxInstance) {
+fn write_instance(s: &mut String, instance: &LoxInstance, printing: &mut Vec<*const ()>) {
     write!(s, "(instance ").unwrap();
     self::write_class_obj(s, &instance.class);
-    write!(s, " (").unwrap();
-    for (name, field) in instance.fields.iter() {
-        write!(s, "({} {}", name, field.pretty_print()).unwrap();
-        write!(s, ")").unwrap();
-    }
-    write!(s, ")").unwrap();
+    // sort the fields so that the output is deterministic
+    let mut fields = instance.fields.iter().collect::<Vec<_>>();
+    fields.sort_by_key(|(name, _)| *name);
+    let fields = fields
+        .iter()
+        .map(|(name, field)| {
+            let mut field_s = format!("({} ", name);
+            self::write_obj(&mut field_s, field, printing);
+            field_s.push(')');
+            field_s
+        })
+        .collect::<Vec<_>>();
+    write!(s, " ({})", fields.join(", ")).unwrap();
     write!(s, ")").unwrap();
 }
 
@@ -0,0 +1,40 @@
+//! Output sinks for `print`
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// In-memory output shared between the interpreter and the host
+///
+/// Give a clone to `Interpreter::with_output` and read the printed text from the other.
+#[derive(Clone, Debug, Default)]
+pub struct SharedBuffer {
+    buf: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Printed text so far (invalid UTF-8 is replaced)
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.buf.borrow()).into_owned()
+    }
+
+    /// Takes the printed text, leaving the buffer empty
+    pub fn take(&self) -> String {
+        let bytes = self.buf.replace(Vec::new());
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
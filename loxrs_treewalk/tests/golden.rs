@@ -0,0 +1,18 @@
+//! Runs the example scripts and checks their `// expect: ..` annotations
+
+use loxrs_treewalk::golden;
+use std::path::Path;
+
+#[test]
+fn examples() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let report = golden::check_dir(&dir).unwrap();
+    assert!(!report.passed.is_empty());
+
+    let failures = report
+        .failed
+        .iter()
+        .map(|(path, failures)| format!("{}:\n    {}", path.display(), failures.join("\n    ")))
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
@@ -2,8 +2,11 @@
 
 use {
     clap::Clap,
-    loxrs_vm::lex::lexer::Lexer,
-    std::io::{self, prelude::*},
+    loxrs_vm::{diagnostics::Emitter, golden, lex::lexer::Lexer, vm::heap::GcConfig},
+    std::{
+        io::{self, prelude::*},
+        path::PathBuf,
+    },
     termcolor::{BufferWriter, Color, ColorChoice, ColorSpec, WriteColor},
 };
 
@@ -35,15 +38,83 @@ impl Cli {
 
 #[derive(Clap, Debug)]
 pub enum SubCommand {
+    #[clap(name = "run")]
+    /// Runs a file, or the REPL if no file is given
+    Run(Run),
     #[clap(name = "lex")]
     /// Paints stdin as a loxrs file
     Lex(Lex),
+    #[clap(name = "test")]
+    /// Runs golden-file tests (`// expect: ..`) in a directory
+    Test(Test),
 }
 
 impl SubCommand {
     pub fn run(&mut self) -> Result<()> {
         match self {
+            SubCommand::Run(cmd) => cmd.run(),
             SubCommand::Lex(cmd) => cmd.run(),
+            SubCommand::Test(cmd) => cmd.run(),
+        }
+    }
+}
+
+/// `loxrs run [file]`
+#[derive(Clap, Debug)]
+pub struct Run {
+    #[clap(parse(from_os_str))]
+    pub file: Option<PathBuf>,
+    /// Collects garbage on every allocation
+    #[clap(long)]
+    pub gc_stress: bool,
+    /// Prints statistics of each garbage collection to stderr
+    #[clap(long)]
+    pub gc_log: bool,
+}
+
+impl Run {
+    pub fn run(&mut self) -> Result<()> {
+        let gc = GcConfig {
+            stress: self.gc_stress,
+            log: self.gc_log,
+        };
+        match self.file {
+            Some(ref file) => loxrs_vm::run_file(file, gc),
+            None => loxrs_vm::run_repl(gc),
+        }
+    }
+}
+
+/// `loxrs test <dir>`
+#[derive(Clap, Debug)]
+pub struct Test {
+    #[clap(parse(from_os_str))]
+    pub dir: PathBuf,
+}
+
+impl Test {
+    pub fn run(&mut self) -> Result<()> {
+        let report = golden::check_dir(&self.dir)?;
+
+        for path in &report.passed {
+            println!("PASS {}", path.display());
+        }
+        for (path, failures) in &report.failed {
+            println!("FAIL {}", path.display());
+            for failure in failures {
+                println!("    {}", failure);
+            }
+        }
+        println!(
+            "\n{} passed, {} failed",
+            report.passed.len(),
+            report.failed.len()
+        );
+
+        if report.failed.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("{} test(s) failed", report.failed.len()))
         }
     }
 }
@@ -73,7 +144,13 @@ impl Lex {
             let mut stks = Vec::with_capacity(100);
             let mut lex = Lexer::new(&src);
             loop {
-                let stk = lex.next_stk()?;
+                let stk = match lex.next_stk() {
+                    Ok(stk) => stk,
+                    Err(why) => {
+                        Emitter::stderr("<stdin>", &src).emit(&why)?;
+                        return Err(anyhow::anyhow!("failed to lex"));
+                    }
+                };
 
                 if stk.tk == Token::Eof {
                     break;
@@ -1,6 +0,0 @@
-//! One-pass compiler of loxrs (bytecode)
-
-pub enum CompileError {
-    X,
-    //
-}
@@ -1,5 +1,620 @@
-mod compiler;
+//! Single-pass compiler of loxrs (bytecode)
+//!
+//! It parses tokens with a Pratt parser and emits instructions on the way; there's no AST.
+//!
+//! ```none
+//! program → decl* EOF ;
+//! decl    → "var" IDENT ( "=" expr )? ";" | stmt ;
+//! stmt    → "print" expr ";" | block | expr ";" ;
+//! block   → "{" decl* "}" ;
+//! ```
+//!
+//! Variables declared at the top level are globals looked up by name at runtime. Variables in
+//! blocks are locals living in stack slots, which are resolved at compile time.
 
-pub fn compile(s: &str) {
-    //
+mod parser;
+
+use thiserror::Error;
+
+use crate::{
+    lex::{lexer::LexError, span::ByteSpan, token::Token},
+    vm::{
+        chunk::{ChunkData, OpCode},
+        value::Value,
+        Vm,
+    },
+};
+
+use self::parser::Parser;
+
+pub type Result<T> = std::result::Result<T, CompileError>;
+
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("{0}")]
+    Lex(#[from] LexError),
+    #[error("expected {expected}, found {found}")]
+    Unexpected {
+        expected: &'static str,
+        found: String,
+        sp: ByteSpan,
+    },
+    #[error("invalid number")]
+    InvalidNumber { sp: ByteSpan },
+    /// Exceeded the range of [`OpCode::OpConst16`]
+    #[error("too many constants in one chunk")]
+    TooManyConsts { sp: ByteSpan },
+    /// Exceeded the range of [`OpCode::OpGetLocal`]
+    #[error("too many local variables in scope")]
+    TooManyLocals { sp: ByteSpan },
+    #[error("variable `{name}` is already declared in this scope")]
+    Redeclared { name: String, sp: ByteSpan },
+    #[error("can't read local variable `{name}` in its own initializer")]
+    OwnInitializer { name: String, sp: ByteSpan },
+    #[error("invalid assignment target")]
+    InvalidAssign { sp: ByteSpan },
+}
+
+/// The maximum number of local variables in scope
+pub const LOCALS_MAX: usize = u8::MAX as usize + 1;
+
+/// Compiles a program into a chunk ending with [`OpCode::OpReturn`]. String constants are
+/// allocated in the heap of the VM, which may collect garbage meanwhile.
+pub fn compile(src: &str, vm: &mut Vm) -> Result<ChunkData> {
+    let mut compiler = Compiler {
+        parser: Parser::new(src)?,
+        chunk: ChunkData::new(),
+        vm,
+        locals: Vec::new(),
+        scope_depth: 0,
+    };
+    while !compiler.parser.check(Token::Eof) {
+        compiler.decl()?;
+    }
+    let eof = compiler.parser.current.sp;
+    compiler.chunk.push_code(OpCode::OpReturn, eof);
+    Ok(compiler.chunk)
+}
+
+/// Binding power of operators, from the lowest to the highest
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    None,
+    Assign,
+    Or,
+    And,
+    Equality,
+    Comparison,
+    Term,
+    Factor,
+    Unary,
+    Call,
+    Primary,
+}
+
+impl Prec {
+    /// One level higher, for the right operand of left-associative operators
+    fn next(self) -> Self {
+        use Prec::*;
+        match self {
+            None => Assign,
+            Assign => Or,
+            Or => And,
+            And => Equality,
+            Equality => Comparison,
+            Comparison => Term,
+            Term => Factor,
+            Factor => Unary,
+            Unary => Call,
+            Call | Primary => Primary,
+        }
+    }
+}
+
+/// Compiles an expression starting with (prefix) or following (infix) the token just consumed.
+/// The flag tells if the expression can be the target of an assignment.
+type ParseFn<'a> = fn(&mut Compiler<'a>, bool) -> Result<()>;
+
+struct ParseRule<'a> {
+    prefix: Option<ParseFn<'a>>,
+    infix: Option<ParseFn<'a>>,
+    /// Precedence as an infix operator
+    prec: Prec,
+}
+
+impl<'a> ParseRule<'a> {
+    fn of(tk: Token) -> Self {
+        let (prefix, infix, prec): (Option<ParseFn<'a>>, Option<ParseFn<'a>>, Prec) = match tk {
+            Token::LParen => (Some(Compiler::grouping), None, Prec::None),
+            Token::Minus => (Some(Compiler::unary), Some(Compiler::binary), Prec::Term),
+            Token::Plus => (None, Some(Compiler::binary), Prec::Term),
+            Token::Slash | Token::Star => (None, Some(Compiler::binary), Prec::Factor),
+            Token::Bang => (Some(Compiler::unary), None, Prec::None),
+            Token::BangEq | Token::EqEq => (None, Some(Compiler::binary), Prec::Equality),
+            Token::Gt | Token::Ge | Token::Lt | Token::Le => {
+                (None, Some(Compiler::binary), Prec::Comparison)
+            }
+            Token::Ident => (Some(Compiler::variable), None, Prec::None),
+            Token::Num => (Some(Compiler::number), None, Prec::None),
+            Token::Str => (Some(Compiler::string), None, Prec::None),
+            Token::True | Token::False | Token::Nil => (Some(Compiler::literal), None, Prec::None),
+            _ => (None, None, Prec::None),
+        };
+        Self {
+            prefix,
+            infix,
+            prec,
+        }
+    }
+}
+
+/// Local variable living in a stack slot
+struct Local<'a> {
+    name: &'a str,
+    /// Scope depth, or `None` while its initializer is compiled
+    depth: Option<usize>,
+}
+
+/// Variable resolved at compile time
+#[derive(Clone, Copy)]
+enum Var {
+    Local(u8),
+    /// Index of the name constant
+    Global(u16),
+}
+
+struct Compiler<'a> {
+    parser: Parser<'a>,
+    chunk: ChunkData,
+    /// Allocates string constants
+    vm: &'a mut Vm,
+    /// Local variables in scope, in the order of stack slots
+    locals: Vec<Local<'a>>,
+    /// The number of blocks surrounding the current code (`0` at the top level)
+    scope_depth: usize,
+}
+
+/// Statements
+impl<'a> Compiler<'a> {
+    fn decl(&mut self) -> Result<()> {
+        if self.parser.consume_if(Token::Var)? {
+            self.var_decl()
+        } else {
+            self.stmt()
+        }
+    }
+
+    fn var_decl(&mut self) -> Result<()> {
+        self.parser.consume(Token::Ident, "variable name")?;
+        let name = self.parser.prev.clone();
+        if self.scope_depth > 0 {
+            self.declare_local(name.slice(self.parser.src()), name.sp)?;
+        }
+
+        if self.parser.consume_if(Token::Eq)? {
+            self.expr()?;
+        } else {
+            // defaults to `nil`
+            self.chunk.push_code(OpCode::OpNil, name.sp);
+        }
+        self.parser.consume(Token::Semicolon, "`;`")?;
+
+        if self.scope_depth > 0 {
+            // the initial value on the stack becomes the local variable
+            self.locals.last_mut().unwrap().depth = Some(self.scope_depth);
+        } else {
+            let ix = self.name_const(name.slice(self.parser.src()), name.sp)?;
+            self.chunk
+                .push_code_u16(OpCode::OpDefineGlobal, ix, name.sp);
+        }
+        Ok(())
+    }
+
+    fn stmt(&mut self) -> Result<()> {
+        let sp = self.parser.current.sp;
+        if self.parser.consume_if(Token::LBrace)? {
+            self.scope_depth += 1;
+            self.block()?;
+            self.end_scope();
+        } else if self.parser.consume_if(Token::Print)? {
+            self.expr()?;
+            self.parser.consume(Token::Semicolon, "`;`")?;
+            self.chunk.push_code(OpCode::OpPrint, sp);
+        } else {
+            self.expr()?;
+            self.parser.consume(Token::Semicolon, "`;`")?;
+            self.chunk.push_code(OpCode::OpPop, sp);
+        }
+        Ok(())
+    }
+
+    /// Compiles declarations until `}`
+    fn block(&mut self) -> Result<()> {
+        while !self.parser.check(Token::RBrace) && !self.parser.check(Token::Eof) {
+            self.decl()?;
+        }
+        self.parser.consume(Token::RBrace, "`}`")
+    }
+
+    /// Pops the local variables of the block just compiled
+    fn end_scope(&mut self) {
+        self.scope_depth -= 1;
+        let sp = self.parser.prev.sp;
+        while let Some(local) = self.locals.last() {
+            if matches!(local.depth, Some(d) if d <= self.scope_depth) {
+                break;
+            }
+            self.locals.pop();
+            self.chunk.push_code(OpCode::OpPop, sp);
+        }
+    }
+}
+
+/// Variables
+impl<'a> Compiler<'a> {
+    /// Adds a local variable which can't be read until its initializer is compiled
+    fn declare_local(&mut self, name: &'a str, sp: ByteSpan) -> Result<()> {
+        for local in self.locals.iter().rev() {
+            if matches!(local.depth, Some(d) if d < self.scope_depth) {
+                break;
+            }
+            if local.name == name {
+                return Err(CompileError::Redeclared {
+                    name: name.to_string(),
+                    sp,
+                });
+            }
+        }
+
+        if self.locals.len() >= LOCALS_MAX {
+            return Err(CompileError::TooManyLocals { sp });
+        }
+        self.locals.push(Local { name, depth: None });
+        Ok(())
+    }
+
+    /// Finds the innermost local variable with the name, or else considers it's a global
+    fn resolve(&mut self, name: &'a str, sp: ByteSpan) -> Result<Var> {
+        let local = self
+            .locals
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, local)| local.name == name);
+        match local {
+            Some((_, Local { depth: None, .. })) => Err(CompileError::OwnInitializer {
+                name: name.to_string(),
+                sp,
+            }),
+            Some((slot, _)) => Ok(Var::Local(slot as u8)),
+            None => Ok(Var::Global(self.name_const(name, sp)?)),
+        }
+    }
+}
+
+/// Expressions
+impl<'a> Compiler<'a> {
+    fn expr(&mut self) -> Result<()> {
+        self.prec(Prec::Assign)
+    }
+
+    /// Compiles an expression made of operators of the precedence or higher
+    fn prec(&mut self, prec: Prec) -> Result<()> {
+        let prefix = ParseRule::of(self.parser.current.tk)
+            .prefix
+            .ok_or_else(|| self.parser.unexpected("expression"))?;
+        self.parser.advance()?;
+        // `a * b = c` is not an assignment to `b`
+        let can_assign = prec <= Prec::Assign;
+        prefix(self, can_assign)?;
+
+        while prec <= ParseRule::of(self.parser.current.tk).prec {
+            self.parser.advance()?;
+            // tokens with precedence are infix operators
+            let infix = ParseRule::of(self.parser.prev.tk).infix.unwrap();
+            infix(self, can_assign)?;
+        }
+
+        let tk = self.parser.current.tk;
+        if can_assign && (tk == Token::Eq || self::compound_op(tk).is_some()) {
+            return Err(CompileError::InvalidAssign {
+                sp: self.parser.current.sp,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn variable(&mut self, can_assign: bool) -> Result<()> {
+        let sp = self.parser.prev.sp;
+        let var = self.resolve(self.parser.prev_slice(), sp)?;
+        let compound = self::compound_op(self.parser.current.tk);
+
+        if can_assign && self.parser.consume_if(Token::Eq)? {
+            self.expr()?;
+            self.push_var(var, true, sp);
+        } else if let (true, Some(code)) = (can_assign, compound) {
+            // `x op= e` is `x = x op e`
+            self.parser.advance()?;
+            let op_sp = self.parser.prev.sp;
+            self.push_var(var, false, sp);
+            self.expr()?;
+            self.chunk.push_code(code, op_sp);
+            self.push_var(var, true, sp);
+        } else {
+            self.push_var(var, false, sp);
+        }
+
+        Ok(())
+    }
+
+    fn number(&mut self, _can_assign: bool) -> Result<()> {
+        let sp = self.parser.prev.sp;
+        let n = self
+            .parser
+            .prev_slice()
+            .parse::<f64>()
+            .map_err(|_| CompileError::InvalidNumber { sp })?;
+        self.push_const(Value::Number(n))
+    }
+
+    fn string(&mut self, _can_assign: bool) -> Result<()> {
+        let slice = self.parser.prev_slice();
+        // strip the quotes
+        let s = self::unescape(&slice[1..slice.len() - 1]);
+        let obj = self.vm.alloc_const_str(&s, self.chunk.consts());
+        self.push_const(Value::Obj(obj))
+    }
+
+    fn literal(&mut self, _can_assign: bool) -> Result<()> {
+        let code = match self.parser.prev.tk {
+            Token::True => OpCode::OpTrue,
+            Token::False => OpCode::OpFalse,
+            Token::Nil => OpCode::OpNil,
+            tk => unreachable!("not a literal: {:?}", tk),
+        };
+        self.chunk.push_code(code, self.parser.prev.sp);
+        Ok(())
+    }
+
+    fn grouping(&mut self, _can_assign: bool) -> Result<()> {
+        self.expr()?;
+        self.parser.consume(Token::RParen, "`)`")
+    }
+
+    fn unary(&mut self, _can_assign: bool) -> Result<()> {
+        let oper = self.parser.prev.clone();
+        self.prec(Prec::Unary)?;
+        let code = match oper.tk {
+            Token::Minus => OpCode::OpNegate,
+            Token::Bang => OpCode::OpNot,
+            tk => unreachable!("not a unary operator: {:?}", tk),
+        };
+        self.chunk.push_code(code, oper.sp);
+        Ok(())
+    }
+
+    fn binary(&mut self, _can_assign: bool) -> Result<()> {
+        let oper = self.parser.prev.clone();
+        self.prec(ParseRule::of(oper.tk).prec.next())?;
+        // `a != b` is `!(a == b)`, `a >= b` is `!(a < b)` and `a <= b` is `!(a > b)`
+        let (code, not) = match oper.tk {
+            Token::Plus => (OpCode::OpAdd, false),
+            Token::Minus => (OpCode::OpSub, false),
+            Token::Star => (OpCode::OpMul, false),
+            Token::Slash => (OpCode::OpDiv, false),
+            Token::EqEq => (OpCode::OpEqual, false),
+            Token::BangEq => (OpCode::OpEqual, true),
+            Token::Gt => (OpCode::OpGreater, false),
+            Token::Ge => (OpCode::OpLess, true),
+            Token::Lt => (OpCode::OpLess, false),
+            Token::Le => (OpCode::OpGreater, true),
+            tk => unreachable!("not a binary operator: {:?}", tk),
+        };
+        self.chunk.push_code(code, oper.sp);
+        if not {
+            self.chunk.push_code(OpCode::OpNot, oper.sp);
+        }
+        Ok(())
+    }
+}
+
+/// Code generation
+impl<'a> Compiler<'a> {
+    /// Adds a constant and emits an instruction to push it
+    fn push_const(&mut self, value: Value) -> Result<()> {
+        let sp = self.parser.prev.sp;
+        let ix = self.chunk.push_const(value);
+        if ix <= u8::MAX as usize {
+            self.chunk.push_ix_u8(ix as u8, sp);
+        } else if ix <= u16::MAX as usize {
+            self.chunk.push_ix_u16(ix as u16, sp);
+        } else {
+            return Err(CompileError::TooManyConsts { sp });
+        }
+        Ok(())
+    }
+
+    /// Emits an instruction to get or set a variable
+    fn push_var(&mut self, var: Var, set: bool, sp: ByteSpan) {
+        match (var, set) {
+            (Var::Local(slot), false) => self.chunk.push_code_u8(OpCode::OpGetLocal, slot, sp),
+            (Var::Local(slot), true) => self.chunk.push_code_u8(OpCode::OpSetLocal, slot, sp),
+            (Var::Global(ix), false) => self.chunk.push_code_u16(OpCode::OpGetGlobal, ix, sp),
+            (Var::Global(ix), true) => self.chunk.push_code_u16(OpCode::OpSetGlobal, ix, sp),
+        }
+    }
+
+    /// Adds the name of a global variable as a string constant
+    fn name_const(&mut self, name: &str, sp: ByteSpan) -> Result<u16> {
+        let obj = self.vm.alloc_const_str(name, self.chunk.consts());
+        let ix = self.chunk.push_const(Value::Obj(obj));
+        if ix <= u16::MAX as usize {
+            Ok(ix as u16)
+        } else {
+            Err(CompileError::TooManyConsts { sp })
+        }
+    }
+}
+
+/// Binary operator of a compound assignment operator such as `+=`
+fn compound_op(tk: Token) -> Option<OpCode> {
+    match tk {
+        Token::PlusEq => Some(OpCode::OpAdd),
+        Token::MinusEq => Some(OpCode::OpSub),
+        Token::StarEq => Some(OpCode::OpMul),
+        Token::SlashEq => Some(OpCode::OpDiv),
+        _ => None,
+    }
+}
+
+/// Replaces escape sequences in a string literal. Unknown escapes are left as they are.
+fn unescape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => res.push('\n'),
+            Some('t') => res.push('\t'),
+            Some('"') => res.push('"'),
+            Some('\\') => res.push('\\'),
+            Some(c) => {
+                res.push('\\');
+                res.push(c);
+            }
+            None => res.push('\\'),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vm::{chunk::OpCode::*, heap::GcConfig};
+
+    fn codes(chunk: &ChunkData) -> Vec<OpCode> {
+        let mut codes = Vec::new();
+        let mut ix = 0;
+        while ix < chunk.bytes().len() {
+            let code = chunk.read_opcode(ix);
+            ix += match code {
+                OpConst8 => 2,
+                OpConst16 | OpDefineGlobal | OpGetGlobal | OpSetGlobal => 3,
+                OpGetLocal | OpSetLocal => 2,
+                _ => 1,
+            };
+            codes.push(code);
+        }
+        codes
+    }
+
+    #[test]
+    fn precedence() {
+        let chunk = compile("print -1 + 2 * (3 - 4);", &mut Vm::new()).unwrap();
+        assert_eq!(
+            codes(&chunk),
+            vec![
+                OpConst8, OpNegate, OpConst8, OpConst8, OpConst8, OpSub, OpMul, OpAdd, OpPrint,
+                OpReturn,
+            ]
+        );
+        assert_eq!(
+            chunk.consts(),
+            &[1.0, 2.0, 3.0, 4.0]
+                .iter()
+                .map(|&n| Value::Number(n))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn strings() {
+        let mut vm = Vm::new();
+        let chunk = compile(r#"print "a" + "a\"b";"#, &mut vm).unwrap();
+        let heap = vm.heap_mut();
+        let consts = chunk.consts();
+        assert_eq!(consts[0], Value::Obj(heap.intern("a")));
+        assert_eq!(consts[1], Value::Obj(heap.intern("a\"b")));
+        assert_eq!(heap.len(), 2);
+    }
+
+    #[test]
+    fn gc_stress() {
+        let mut vm = Vm::new();
+        vm.set_gc_config(GcConfig {
+            stress: true,
+            log: false,
+        });
+        vm.heap_mut().intern("garbage");
+        let src = r#"var a = "x"; print a + "y"; print "z";"#;
+        let chunk = compile(src, &mut vm).unwrap();
+
+        // compile-time allocations collect garbage, keeping the constants compiled so far
+        let stats = vm.gc_stats();
+        assert!(stats.collections >= 4, "{:?}", stats);
+        assert_eq!(stats.collected, 1);
+        let strings = chunk
+            .consts()
+            .iter()
+            .map(|v| v.as_str(vm.heap()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(strings, vec!["x", "a", "a", "y", "z"]);
+    }
+
+    #[test]
+    fn variables() {
+        let mut vm = Vm::new();
+        let chunk = compile("var a = 1; { var b = a; b = 2; }", &mut vm).unwrap();
+        assert_eq!(
+            codes(&chunk),
+            vec![
+                OpConst8,
+                OpDefineGlobal,
+                OpGetGlobal,
+                OpConst8,
+                OpSetLocal,
+                OpPop,
+                OpPop,
+                OpReturn,
+            ]
+        );
+
+        let chunk = compile("{ var a = 1; a += 2; }", &mut vm).unwrap();
+        assert_eq!(
+            codes(&chunk),
+            vec![OpConst8, OpGetLocal, OpConst8, OpAdd, OpSetLocal, OpPop, OpPop, OpReturn,]
+        );
+
+        assert!(matches!(
+            compile("{ var a = 1; var a = 2; }", &mut vm),
+            Err(CompileError::Redeclared { name, .. }) if name == "a"
+        ));
+        // shadowing
+        assert!(compile("{ var a = 1; { var a = 2; } }", &mut vm).is_ok());
+        assert!(compile("var a = 1; var a = 2;", &mut vm).is_ok());
+    }
+
+    #[test]
+    fn errors() {
+        match compile("print (1 + 2;", &mut Vm::new()) {
+            Err(CompileError::Unexpected {
+                expected,
+                found,
+                sp,
+            }) => {
+                assert_eq!((expected, found.as_str()), ("`)`", "`;`"));
+                assert_eq!(sp, ByteSpan::from([12, 13]));
+            }
+            result => panic!("expected unexpected token error: {:?}", result),
+        }
+        assert!(matches!(
+            compile("1 +", &mut Vm::new()),
+            Err(CompileError::Unexpected { found, .. }) if found == "end of file"
+        ));
+    }
 }
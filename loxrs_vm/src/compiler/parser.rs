@@ -0,0 +1,95 @@
+//! Token cursor of the compiler
+
+use crate::{
+    compiler::{CompileError, Result},
+    lex::{
+        lexer::Lexer,
+        span::ByteSpan,
+        token::{SpanToken, Token},
+    },
+};
+
+/// Looks one token ahead, skipping whitespaces and comments
+pub struct Parser<'a> {
+    src: &'a str,
+    lex: Lexer<'a>,
+    /// The token consumed last
+    pub prev: SpanToken,
+    /// The token to be consumed next
+    pub current: SpanToken,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(src: &'a str) -> Result<Self> {
+        let mut me = Self {
+            src,
+            lex: Lexer::new(src),
+            prev: SpanToken::new(Token::Eof, ByteSpan::default()),
+            current: SpanToken::new(Token::Eof, ByteSpan::default()),
+        };
+        me.current = me.next_stk()?;
+        Ok(me)
+    }
+
+    fn next_stk(&mut self) -> Result<SpanToken> {
+        loop {
+            let stk = self.lex.next_stk()?;
+            match stk.tk {
+                Token::Ws | Token::LineComment | Token::RangeComment => {}
+                _ => return Ok(stk),
+            }
+        }
+    }
+
+    /// Consumes the current token
+    pub fn advance(&mut self) -> Result<()> {
+        let next = self.next_stk()?;
+        self.prev = std::mem::replace(&mut self.current, next);
+        Ok(())
+    }
+
+    pub fn check(&self, tk: Token) -> bool {
+        self.current.tk == tk
+    }
+
+    /// Consumes the current token if it's the expected one
+    pub fn consume_if(&mut self, tk: Token) -> Result<bool> {
+        if self.check(tk) {
+            self.advance()?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Consumes the expected token or fails
+    pub fn consume(&mut self, tk: Token, expected: &'static str) -> Result<()> {
+        if self.check(tk) {
+            self.advance()
+        } else {
+            Err(self.unexpected(expected))
+        }
+    }
+
+    /// Error at the current token
+    pub fn unexpected(&self, expected: &'static str) -> CompileError {
+        let found = match self.current.tk {
+            Token::Eof => "end of file".to_string(),
+            _ => format!("`{}`", self.current.slice(self.src)),
+        };
+        CompileError::Unexpected {
+            expected,
+            found,
+            sp: self.current.sp,
+        }
+    }
+
+    pub fn src(&self) -> &'a str {
+        self.src
+    }
+
+    /// Source string of the token consumed last
+    pub fn prev_slice(&self) -> &'a str {
+        self.prev.slice(self.src)
+    }
+}
@@ -0,0 +1,258 @@
+//! Human-readable error reports with source snippets, in the style of `rustc`
+//!
+//! ```none
+//! error: unexpected byte: `$`
+//!  --> <stdin>:1:5
+//!   |
+//! 1 | 3 + $;
+//!   |     ^
+//! ```
+
+use {
+    std::io::{self, IsTerminal},
+    termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor},
+};
+
+use crate::{
+    compiler::CompileError,
+    lex::{
+        lexer::LexError,
+        span::{ByteSpan, SrcPos},
+    },
+    vm::VmError,
+};
+
+/// An error report: a primary message at some source span and optional notes
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub msg: String,
+    pub sp: Option<ByteSpan>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(msg: impl Into<String>, sp: Option<ByteSpan>) -> Self {
+        Self {
+            msg: msg.into(),
+            sp,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Self {
+        match err {
+            LexError::UnterminatedString { start } => {
+                Diagnostic::new("unterminated string", Some(ByteSpan::new(*start, *start)))
+                    .note("the string starts here")
+            }
+            LexError::UnterminatedComment { start } => {
+                Diagnostic::new("unterminated comment", Some(ByteSpan::new(*start, *start)))
+                    .note("the comment starts here")
+            }
+            LexError::UnexpectedEof() => Diagnostic::new("unexpected EoF", None),
+            LexError::UnexpectedByte { pos, byte } => Diagnostic::new(
+                format!("unexpected byte: `{}`", *byte as char),
+                Some(ByteSpan::new(*pos, *pos)),
+            ),
+        }
+    }
+}
+
+impl From<&CompileError> for Diagnostic {
+    fn from(err: &CompileError) -> Self {
+        match err {
+            CompileError::Lex(err) => Diagnostic::from(err),
+            CompileError::OwnInitializer { sp, .. } => Diagnostic::new(err.to_string(), Some(*sp))
+                .note("the variable is declared but not initialized yet"),
+            CompileError::InvalidAssign { sp } => Diagnostic::new(err.to_string(), Some(*sp))
+                .note("only variables can be assigned to"),
+            CompileError::Unexpected { sp, .. }
+            | CompileError::InvalidNumber { sp }
+            | CompileError::TooManyConsts { sp }
+            | CompileError::TooManyLocals { sp }
+            | CompileError::Redeclared { sp, .. } => Diagnostic::new(err.to_string(), Some(*sp)),
+        }
+    }
+}
+
+impl From<&VmError> for Diagnostic {
+    fn from(err: &VmError) -> Self {
+        match err {
+            VmError::CompileError(err) => Diagnostic::from(err),
+            VmError::RuntimeError { sp, .. } => Diagnostic::new(err.to_string(), Some(*sp)),
+            _ => Diagnostic::new(err.to_string(), None),
+        }
+    }
+}
+
+/// Renders [`Diagnostic`]s on a source file
+pub struct Emitter<'a> {
+    path: &'a str,
+    src: &'a str,
+    out: StandardStream,
+}
+
+impl<'a> Emitter<'a> {
+    /// Writes to stderr, colored when stdout is a terminal. Piping the output of a script turns
+    /// the color off along with it. Use [`Emitter::stderr_with_color`] to choose otherwise.
+    pub fn stderr(path: &'a str, src: &'a str) -> Self {
+        let color = if io::stdout().is_terminal() {
+            ColorChoice::Auto
+        } else {
+            ColorChoice::Never
+        };
+        Self::stderr_with_color(path, src, color)
+    }
+
+    /// Writes to stderr, colored as chosen
+    pub fn stderr_with_color(path: &'a str, src: &'a str, color: ColorChoice) -> Self {
+        Self {
+            path,
+            src,
+            out: StandardStream::stderr(color),
+        }
+    }
+
+    pub fn emit(&mut self, diag: impl Into<Diagnostic>) -> io::Result<()> {
+        self::render(&mut self.out, self.path, self.src, &diag.into())
+    }
+}
+
+/// Renders a [`Diagnostic`] with the source line it points to
+pub fn render(
+    out: &mut impl WriteColor,
+    path: &str,
+    src: &str,
+    diag: &Diagnostic,
+) -> io::Result<()> {
+    let mut blue = ColorSpec::new();
+    blue.set_fg(Some(Color::Blue)).set_bold(true);
+    let mut red = ColorSpec::new();
+    red.set_fg(Some(Color::Red)).set_bold(true);
+
+    // error: <msg>
+    out.set_color(&red)?;
+    write!(out, "error")?;
+    out.set_color(ColorSpec::new().set_bold(true))?;
+    writeln!(out, ": {}", diag.msg)?;
+    out.reset()?;
+
+    let sp = match diag.sp {
+        Some(sp) => sp,
+        None => {
+            self::write_arrow(out, &blue, 0, path)?;
+            return self::write_notes(out, &blue, 0, &diag.notes);
+        }
+    };
+
+    let pos = SrcPos::locate(src, sp.lo);
+    let line = src.lines().nth(pos.ln() - 1).unwrap_or("");
+    let width = pos.ln().to_string().len();
+
+    //  --> path:ln:col
+    //   |
+    self::write_arrow(
+        out,
+        &blue,
+        width,
+        &format!("{}:{}:{}", path, pos.ln(), pos.col()),
+    )?;
+    self::write_gutter(out, &blue, width, "")?;
+    writeln!(out)?;
+
+    // ln | <source line>
+    self::write_gutter(out, &blue, width, &pos.ln().to_string())?;
+    writeln!(out, " {}", line)?;
+
+    //    |    ^^^
+    self::write_gutter(out, &blue, width, "")?;
+    // keep tabs so that the caret is aligned
+    let pad = line
+        .chars()
+        .take(pos.col() - 1)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+    // the underline doesn't go beyond the line
+    let len = line
+        .chars()
+        .skip(pos.col() - 1)
+        .take(src[sp.lo.0..sp.hi.0].chars().count())
+        .count()
+        .max(1);
+    write!(out, " {}", pad)?;
+    out.set_color(&red)?;
+    writeln!(out, "{}", "^".repeat(len))?;
+    out.reset()?;
+
+    self::write_notes(out, &blue, width, &diag.notes)
+}
+
+/// ` --> <location>`
+fn write_arrow(
+    out: &mut impl WriteColor,
+    color: &ColorSpec,
+    width: usize,
+    location: &str,
+) -> io::Result<()> {
+    out.set_color(color)?;
+    write!(out, "{:width$}--> ", "", width = width)?;
+    out.reset()?;
+    writeln!(out, "{}", location)
+}
+
+/// `<ln> |`
+fn write_gutter(
+    out: &mut impl WriteColor,
+    color: &ColorSpec,
+    width: usize,
+    ln: &str,
+) -> io::Result<()> {
+    out.set_color(color)?;
+    write!(out, "{:>width$} |", ln, width = width)?;
+    out.reset()
+}
+
+/// `  = note: <note>`
+fn write_notes(
+    out: &mut impl WriteColor,
+    color: &ColorSpec,
+    width: usize,
+    notes: &[String],
+) -> io::Result<()> {
+    for note in notes {
+        out.set_color(color)?;
+        write!(out, "{:width$} = ", "", width = width)?;
+        out.reset()?;
+        writeln!(out, "note: {}", note)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, termcolor::NoColor};
+
+    #[test]
+    fn underline_span() {
+        let src = "1 + 2;\nvar x = abc * 3;\n";
+        let diag = Diagnostic::new("undefined variable", Some(ByteSpan::from([15, 18])));
+
+        let mut out = NoColor::new(Vec::new());
+        render(&mut out, "test.lox", src, &diag).unwrap();
+
+        let expected = r#"error: undefined variable
+ --> test.lox:2:9
+  |
+2 | var x = abc * 3;
+  |         ^^^
+"#;
+        assert_eq!(String::from_utf8(out.into_inner()).unwrap(), expected);
+    }
+}
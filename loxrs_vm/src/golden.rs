@@ -0,0 +1,197 @@
+//! Golden-file tests: runs `.lox` scripts and compares the results with annotations
+//!
+//! ```none
+//! print 1 + 2; // expect: 3
+//! print -nil; // expect runtime error: <message>
+//! ```
+//!
+//! Scripts that fail to compile can be annotated with `// expect error: <message>`.
+
+use std::{
+    cell::RefCell,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::vm::{heap::GcConfig, Vm, VmError};
+
+const EXPECT_OUTPUT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const EXPECT_ERROR: &str = "// expect error: ";
+
+/// Annotations in a script
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Expectations {
+    /// Printed lines in order
+    pub output: Vec<String>,
+    pub runtime_error: Option<String>,
+    pub compile_error: Option<String>,
+}
+
+impl Expectations {
+    pub fn parse(src: &str) -> Self {
+        let mut expects = Self::default();
+        for line in src.lines() {
+            if let Some(s) = self::annotation(line, EXPECT_OUTPUT) {
+                expects.output.push(s);
+            } else if let Some(s) = self::annotation(line, EXPECT_RUNTIME_ERROR) {
+                expects.runtime_error = Some(s);
+            } else if let Some(s) = self::annotation(line, EXPECT_ERROR) {
+                expects.compile_error = Some(s);
+            }
+        }
+        expects
+    }
+}
+
+fn annotation(line: &str, prefix: &str) -> Option<String> {
+    line.find(prefix)
+        .map(|i| line[i + prefix.len()..].trim_end().to_string())
+}
+
+/// Output of the VM captured for comparison
+#[derive(Clone, Default)]
+struct Capture {
+    buf: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs a script and returns mismatches with the expectations (empty if it passed)
+pub fn check(src: &str) -> Vec<String> {
+    self::check_with(src, GcConfig::default())
+}
+
+/// [`check`] with options of the garbage collector
+pub fn check_with(src: &str, gc: GcConfig) -> Vec<String> {
+    let expects = Expectations::parse(src);
+    let out = Capture::default();
+    let mut vm = Vm::with_output(Box::new(out.clone()));
+    vm.set_gc_config(gc);
+    let result = crate::interpret(&mut vm, src);
+
+    let mut failures = Vec::new();
+    let output = String::from_utf8_lossy(&out.buf.borrow()).into_owned();
+    let output = output.lines().collect::<Vec<_>>();
+    for i in 0..output.len().max(expects.output.len()) {
+        match (expects.output.get(i), output.get(i)) {
+            (Some(expected), Some(found)) if expected == found => {}
+            (Some(expected), Some(found)) => {
+                failures.push(format!("expected output `{}`, found `{}`", expected, found))
+            }
+            (Some(expected), None) => {
+                failures.push(format!("expected output `{}`, found nothing", expected))
+            }
+            (None, Some(found)) => failures.push(format!("unexpected output `{}`", found)),
+            (None, None) => unreachable!(),
+        }
+    }
+
+    // errors other than compile errors are considered as runtime errors
+    let (runtime_error, compile_error) = match result {
+        Ok(()) => (None, None),
+        Err(why) => match why.downcast_ref::<VmError>() {
+            Some(VmError::CompileError(_)) => (None, Some(why.to_string())),
+            _ => (Some(why.to_string()), None),
+        },
+    };
+    self::check_error(
+        &mut failures,
+        "runtime error",
+        &expects.runtime_error,
+        &runtime_error,
+    );
+    self::check_error(
+        &mut failures,
+        "error",
+        &expects.compile_error,
+        &compile_error,
+    );
+
+    failures
+}
+
+fn check_error(
+    failures: &mut Vec<String>,
+    name: &str,
+    expected: &Option<String>,
+    found: &Option<String>,
+) {
+    match (expected, found) {
+        (Some(expected), Some(found)) if expected == found => {}
+        (Some(expected), Some(found)) => failures.push(format!(
+            "expected {} `{}`, found `{}`",
+            name, expected, found
+        )),
+        (Some(expected), None) => {
+            failures.push(format!("expected {} `{}`, found nothing", name, expected))
+        }
+        (None, Some(found)) => failures.push(format!("unexpected {} `{}`", name, found)),
+        (None, None) => {}
+    }
+}
+
+/// Results of running scripts in a directory
+#[derive(Debug, Default)]
+pub struct Report {
+    pub passed: Vec<PathBuf>,
+    pub failed: Vec<(PathBuf, Vec<String>)>,
+}
+
+/// Checks all the `.lox` files in a directory recursively, in alphabetical order
+pub fn check_dir(dir: &Path) -> io::Result<Report> {
+    self::check_dir_with(dir, GcConfig::default())
+}
+
+/// [`check_dir`] with options of the garbage collector
+pub fn check_dir_with(dir: &Path, gc: GcConfig) -> io::Result<Report> {
+    let mut report = Report::default();
+    for path in self::lox_files(dir)? {
+        let src = fs::read_to_string(&path)?;
+        let failures = self::check_with(&src, gc);
+        if failures.is_empty() {
+            report.passed.push(path);
+        } else {
+            report.failed.push((path, failures));
+        }
+    }
+    Ok(report)
+}
+
+fn lox_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            files.extend(self::lox_files(&path)?);
+        } else if path.extension() == Some("lox".as_ref()) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_expectations() {
+        let src = "print 1; // expect: 1\nprint x; // expect runtime error: oops\n";
+        let expects = Expectations::parse(src);
+        assert_eq!(expects.output, vec!["1".to_string()]);
+        assert_eq!(expects.runtime_error, Some("oops".to_string()));
+        assert_eq!(expects.compile_error, None);
+    }
+}
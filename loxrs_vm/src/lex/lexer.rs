@@ -254,7 +254,9 @@ impl<'a> LexState<'a> {
             b's' if word == b"self" => Token::SelfSmall,
             b'S' if word == b"Self" => Token::SelfCapital,
             // statements
+            b'p' if word == b"print" => Token::Print,
             b'r' if word == b"ret" => Token::Return,
+            b'v' if word == b"var" => Token::Var,
             _ => Token::Ident,
         };
 
@@ -295,10 +297,10 @@ impl<'a> LexState<'a> {
             b';' => Token::Semicolon,
             b',' => Token::Comma,
             b'.' => Token::Dot,
-            b'+' => Token::Plus,
-            b'-' => Token::Minus,
-            b'*' => Token::Star,
-            b'/' => Token::Slash,
+            b'+' => self.one_two(Token::Plus, b'=', Token::PlusEq),
+            b'-' => self.one_two(Token::Minus, b'=', Token::MinusEq),
+            b'*' => self.one_two(Token::Star, b'=', Token::StarEq),
+            b'/' => self.one_two(Token::Slash, b'=', Token::SlashEq),
             b'!' => self.one_two(Token::Bang, b'=', Token::BangEq),
             b'=' => self.one_two(Token::Eq, b'=', Token::EqEq),
             b'<' => self.one_two(Token::Lt, b'=', Token::Le),
@@ -460,6 +462,23 @@ mod tests {
         )
     }
 
+    #[test]
+    fn compound_assignments() -> Result<()> {
+        self::match_tokens(
+            "+= -= *= /=+",
+            &[
+                Token::PlusEq,
+                Token::Ws,
+                Token::MinusEq,
+                Token::Ws,
+                Token::StarEq,
+                Token::Ws,
+                Token::SlashEq,
+                Token::Plus,
+            ],
+        )
+    }
+
     #[test]
     fn number() -> Result<()> {
         let src = "3.14 * 0.25";
@@ -491,7 +510,7 @@ mod tests {
     #[test]
     fn keywords() -> Result<()> {
         self::match_tokens(
-            "my_ident if else for while loop true false nil self Self ret",
+            "my_ident if else for while loop true false nil self Self print ret var",
             &[
                 Token::Ident,
                 Token::Ws,
@@ -515,7 +534,11 @@ mod tests {
                 Token::Ws,
                 Token::SelfCapital,
                 Token::Ws,
+                Token::Print,
+                Token::Ws,
                 Token::Return,
+                Token::Ws,
+                Token::Var,
             ],
         )
     }
@@ -39,6 +39,10 @@ impl ByteSpan {
     pub fn len(&self) -> usize {
         self.hi.0 - self.lo.0
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 /// Human friendly source position representation
@@ -69,6 +73,19 @@ impl SrcPos {
 }
 
 impl SrcPos {
+    /// Converts a byte position in the source string into line and column (counted in `char`s)
+    pub fn locate(src: &str, pos: BytePos) -> Self {
+        let mut src_pos = Self::initial();
+        for c in src[..pos.0.min(src.len())].chars() {
+            if c == '\n' {
+                src_pos.inc_ln();
+            } else {
+                src_pos.inc_col();
+            }
+        }
+        src_pos
+    }
+
     pub fn inc_ln(&mut self) {
         self.ln += 1;
         self.col = 1;
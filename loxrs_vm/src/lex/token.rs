@@ -30,10 +30,16 @@ pub enum Token {
     // arithmetic
     Minus,
     Plus,
-    // MinusEqual,
-    // PlusEqual,
     Slash,
     Star,
+    /// -=
+    MinusEq,
+    /// +=
+    PlusEq,
+    /// /=
+    SlashEq,
+    /// *=
+    StarEq,
 
     // ----------------------------------------
     // comparison
@@ -81,6 +87,7 @@ pub enum Token {
     SelfCapital,
 
     // statements
+    Print,
     Return,
     Var,
     Fn,
@@ -1,8 +1,9 @@
 //! Loxrs bytecode interpreter
 
 pub mod compiler;
+pub mod diagnostics;
+pub mod golden;
 pub mod lex;
-pub mod parse;
 pub mod vm;
 
 #[macro_use]
@@ -20,21 +21,39 @@ use {
     },
 };
 
-use crate::vm::{Vm, VmError};
+use crate::{
+    diagnostics::{Diagnostic, Emitter},
+    vm::{heap::GcConfig, Vm, VmError},
+};
 
+/// Compiles a program and runs it
 pub fn interpret(vm: &mut Vm, src: &str) -> Result<()> {
-    // let x = compiler::compile(src);
-    Ok(())
+    let chunk = compiler::compile(src, vm).map_err(VmError::CompileError)?;
+    vm.run_chunk(chunk)
 }
 
-pub fn run_file(file: &Path) -> Result<()> {
+/// Reports an error from [`interpret`], pointing at the source if it's from the program
+pub fn emit_error(path: &str, src: &str, why: &Error) -> io::Result<()> {
+    let diag = match why.downcast_ref::<VmError>() {
+        Some(err) => Diagnostic::from(err),
+        None => Diagnostic::new(why.to_string(), None),
+    };
+    Emitter::stderr(path, src).emit(diag)
+}
+
+pub fn run_file(file: &Path, gc: GcConfig) -> Result<()> {
     let s = fs::read_to_string(file)
         .with_context(|| format!("when opening file {}", file.display()))?;
     let mut vm = Vm::new();
-    self::interpret(&mut vm, &s)
+    vm.set_gc_config(gc);
+    if let Err(why) = self::interpret(&mut vm, &s) {
+        self::emit_error(&file.display().to_string(), &s, &why)?;
+        return Err(anyhow!("failed to run {}", file.display()));
+    }
+    Ok(())
 }
 
-pub fn run_repl() -> Result<()> {
+pub fn run_repl(gc: GcConfig) -> Result<()> {
     println!("loxrs REPL (bytecode) [press q<Enter> or Ctrl-c to quit]");
     let prompt_str = "> ";
 
@@ -46,25 +65,30 @@ pub fn run_repl() -> Result<()> {
 
     let mut line = String::new();
     let mut vm = Vm::new();
+    vm.set_gc_config(gc);
 
     loop {
         print!("{}", prompt_str);
         out.flush().context("error when flushing stdout")?;
 
         line.clear();
-        input.read_line(&mut line).context("when reading stdin")?;
+        if input.read_line(&mut line).context("when reading stdin")? == 0 {
+            // end of input
+            break;
+        }
 
         match line.trim_end() {
             "q" | "quit" => {
                 break;
             }
-            line => match self::interpret(&mut vm, line) {
-                Err(why) => eprintln!("Error: {}", why),
-                Ok(()) => {}
-            },
+            line => {
+                if let Err(why) = self::interpret(&mut vm, line) {
+                    self::emit_error("<stdin>", line, &why)?;
+                    // globals are kept, but not the temporaries of the failed line
+                    vm.clear_stack();
+                }
+            }
         }
-
-        vm.clear_stack();
     }
 
     Ok(())
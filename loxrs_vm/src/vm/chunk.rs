@@ -1,5 +1,7 @@
 use std::{io::prelude::*, mem::transmute};
 
+use crate::{lex::span::ByteSpan, vm::value::Value};
+
 /// Operation code to the virtual machine
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Ord, PartialOrd)]
 pub enum OpCode {
@@ -10,11 +12,36 @@ pub enum OpCode {
     /// Followed by a two bytes index
     OpConst16,
 
+    OpNil,
+    OpTrue,
+    OpFalse,
+
     OpNegate,
     OpAdd,
     OpSub,
     OpMul,
     OpDiv,
+
+    OpNot,
+    OpEqual,
+    OpGreater,
+    OpLess,
+
+    /// Pops a value and prints it
+    OpPrint,
+    /// Pops a value (the result of an expression statement)
+    OpPop,
+
+    /// Followed by a two bytes index of the name constant. Pops the initial value.
+    OpDefineGlobal,
+    /// Followed by a two bytes index of the name constant
+    OpGetGlobal,
+    /// Followed by a two bytes index of the name constant. Leaves the assigned value.
+    OpSetGlobal,
+    /// Followed by a byte stack slot
+    OpGetLocal,
+    /// Followed by a byte stack slot. Leaves the assigned value.
+    OpSetLocal,
 }
 
 impl Into<u8> for OpCode {
@@ -23,24 +50,20 @@ impl Into<u8> for OpCode {
     }
 }
 
-/// Constant value (it's f64 for now)
-pub type Value = f64;
-
 /// Chunk of instructions ([`OpCode`]s)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct ChunkData {
     /// Upcated bytes
     bytes: Vec<u8>,
     /// Constant values stored
     consts: Vec<Value>,
+    /// Source span of each byte, for error reports
+    spans: Vec<ByteSpan>,
 }
 
 impl ChunkData {
     pub fn new() -> Self {
-        Self {
-            bytes: Vec::new(),
-            consts: Vec::new(),
-        }
+        Self::default()
     }
 }
 
@@ -52,13 +75,15 @@ impl ChunkData {
     }
 
     #[inline(always)]
-    pub fn consts(&mut self) -> &Vec<Value> {
+    pub fn consts(&self) -> &Vec<Value> {
         &self.consts
     }
 
+    /// Adds a constant and returns its index
     #[inline(always)]
-    pub fn push_const(&mut self, value: Value) {
-        self.consts.push(value)
+    pub fn push_const(&mut self, value: Value) -> usize {
+        self.consts.push(value);
+        self.consts.len() - 1
     }
 
     #[inline(always)]
@@ -75,39 +100,54 @@ impl ChunkData {
     pub fn read_u16(&self, ix: usize) -> u16 {
         ((self.bytes[ix] as u16) << 8) | (self.bytes[ix + 1] as u16)
     }
+
+    /// Source span of the code the byte is compiled from
+    pub fn span(&self, ix: usize) -> ByteSpan {
+        self.spans[ix]
+    }
 }
 
 /// Write
 impl ChunkData {
     #[inline(always)]
-    pub fn push_code(&mut self, code: OpCode) {
-        self.bytes.push(code as u8);
+    fn push_byte(&mut self, byte: u8, sp: ByteSpan) {
+        self.bytes.push(byte);
+        self.spans.push(sp);
+    }
+
+    #[inline(always)]
+    pub fn push_code(&mut self, code: OpCode, sp: ByteSpan) {
+        self.push_byte(code as u8, sp);
     }
 
     #[inline(always)]
-    pub fn push_ix_u8(&mut self, x: u8) {
-        self.bytes.push(OpCode::OpConst8 as u8);
-        self.bytes.push(x);
+    pub fn push_ix_u8(&mut self, x: u8, sp: ByteSpan) {
+        self.push_byte(OpCode::OpConst8 as u8, sp);
+        self.push_byte(x, sp);
     }
 
     #[inline(always)]
-    pub fn push_ix_u16(&mut self, x: u16) {
-        self.bytes.push(OpCode::OpConst16 as u8);
+    pub fn push_ix_u16(&mut self, x: u16, sp: ByteSpan) {
+        self.push_byte(OpCode::OpConst16 as u8, sp);
         // higher 8 bits
-        self.bytes.push((x >> 8) as u8);
+        self.push_byte((x >> 8) as u8, sp);
         // lower 8 bits
-        self.bytes.push(x as u8);
+        self.push_byte(x as u8, sp);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::mem::size_of;
+    /// Pushes an instruction followed by a byte operand
+    #[inline(always)]
+    pub fn push_code_u8(&mut self, code: OpCode, x: u8, sp: ByteSpan) {
+        self.push_byte(code as u8, sp);
+        self.push_byte(x, sp);
+    }
 
-    #[test]
-    fn test_memory_sizes() {
-        assert_eq!(1, size_of::<OpCode>());
+    /// Pushes an instruction followed by a two bytes operand
+    #[inline(always)]
+    pub fn push_code_u16(&mut self, code: OpCode, x: u16, sp: ByteSpan) {
+        self.push_byte(code as u8, sp);
+        self.push_byte((x >> 8) as u8, sp);
+        self.push_byte(x as u8, sp);
     }
 }
 
@@ -159,7 +199,28 @@ impl DebugPrint for ChunkData {
                     iter.next();
                 }
 
-                OpNegate | OpAdd | OpSub | OpMul | OpDiv | OpReturn => {
+                OpDefineGlobal | OpGetGlobal | OpSetGlobal => {
+                    let ix = self.read_u16(offset + 1);
+                    writeln!(
+                        out,
+                        "{:?}: idx = {}, name = {:?}",
+                        code,
+                        ix,
+                        self.consts.get(ix as usize)
+                    )
+                    .unwrap();
+
+                    iter.next();
+                    iter.next();
+                }
+
+                OpGetLocal | OpSetLocal => {
+                    writeln!(out, "{:?}: slot = {}", code, self.read_u8(offset + 1)).unwrap();
+                    iter.next();
+                }
+
+                OpNil | OpTrue | OpFalse | OpNegate | OpAdd | OpSub | OpMul | OpDiv | OpNot
+                | OpEqual | OpGreater | OpLess | OpPrint | OpPop | OpReturn => {
                     writeln!(out, "{:?}", code).unwrap()
                 }
             }
@@ -168,3 +229,14 @@ impl DebugPrint for ChunkData {
         out.flush().unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::size_of;
+
+    #[test]
+    fn test_memory_sizes() {
+        assert_eq!(1, size_of::<OpCode>());
+    }
+}
@@ -0,0 +1,295 @@
+//! Objects allocated at runtime (or compile time, as constants) and their mark-sweep collector
+//!
+//! The heap doesn't know its roots. The [`Vm`](crate::vm::Vm) checks [`Heap::should_collect`]
+//! before allocating and calls [`Heap::collect`] with the values it can reach. The compiler
+//! allocates through the VM too, adding the constants of the chunk being compiled to the roots.
+
+use std::{collections::HashMap, mem::size_of, rc::Rc};
+
+use crate::vm::value::Value;
+
+/// Heap size that triggers the first collection
+pub const GC_INITIAL_THRESHOLD: usize = 1024 * 1024;
+
+/// The next collection runs when the heap grows to this times the size after a collection
+pub const GC_GROW_FACTOR: usize = 2;
+
+/// Handle to an object in the [`Heap`]. Strings are interned, so two string handles are equal iff
+/// the strings are equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+/// Heap-allocated value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Obj {
+    Str(Rc<str>),
+}
+
+impl Obj {
+    /// Approximate number of bytes owned by the object
+    fn size(&self) -> usize {
+        size_of::<Slot>()
+            + match self {
+                Obj::Str(s) => s.len(),
+            }
+    }
+}
+
+/// Options of the collector
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GcConfig {
+    /// Collect on every allocation, to find objects that are not rooted
+    pub stress: bool,
+    /// Print statistics of each collection to stderr
+    pub log: bool,
+}
+
+/// Memory statistics of the [`Heap`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GcStats {
+    /// The number of objects alive
+    pub objects: usize,
+    /// Approximate number of bytes owned by the objects alive
+    pub bytes: usize,
+    /// The number of collections run so far
+    pub collections: usize,
+    /// The number of objects freed by the collector so far
+    pub collected: usize,
+}
+
+#[derive(Debug)]
+struct Slot {
+    obj: Option<Obj>,
+    marked: bool,
+}
+
+/// Owner of all the objects
+#[derive(Debug)]
+pub struct Heap {
+    slots: Vec<Slot>,
+    /// Indices of empty slots
+    free: Vec<usize>,
+    /// Interning table. It doesn't keep the strings alive.
+    strings: HashMap<Rc<str>, ObjRef>,
+    /// Objects marked but not traced yet
+    gray: Vec<ObjRef>,
+    bytes: usize,
+    next_gc: usize,
+    config: GcConfig,
+    stats: GcStats,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            strings: HashMap::new(),
+            gray: Vec::new(),
+            bytes: 0,
+            next_gc: GC_INITIAL_THRESHOLD,
+            config: GcConfig::default(),
+            stats: GcStats::default(),
+        }
+    }
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of objects alive
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn config(&self) -> GcConfig {
+        self.config
+    }
+
+    pub fn set_config(&mut self, config: GcConfig) {
+        self.config = config;
+    }
+
+    pub fn stats(&self) -> GcStats {
+        GcStats {
+            objects: self.len(),
+            bytes: self.bytes,
+            ..self.stats
+        }
+    }
+
+    pub fn get(&self, obj: ObjRef) -> &Obj {
+        self.slots[obj.0]
+            .obj
+            .as_ref()
+            .expect("reference to a freed object")
+    }
+
+    pub fn as_str(&self, obj: ObjRef) -> Option<&str> {
+        match self.get(obj) {
+            Obj::Str(s) => Some(s),
+        }
+    }
+
+    /// Returns the string object with the content, allocating it only if it's not interned yet
+    pub fn intern(&mut self, s: &str) -> ObjRef {
+        match self.strings.get(s) {
+            Some(&obj) => obj,
+            None => self.alloc_str(Rc::from(s)),
+        }
+    }
+
+    /// [`Heap::intern`] taking the ownership of the string
+    pub fn intern_owned(&mut self, s: String) -> ObjRef {
+        match self.strings.get(s.as_str()) {
+            Some(&obj) => obj,
+            None => self.alloc_str(Rc::from(s)),
+        }
+    }
+
+    fn alloc_str(&mut self, s: Rc<str>) -> ObjRef {
+        let obj = self.alloc(Obj::Str(s.clone()));
+        self.strings.insert(s, obj);
+        obj
+    }
+
+    fn alloc(&mut self, obj: Obj) -> ObjRef {
+        self.bytes += obj.size();
+        let slot = Slot {
+            obj: Some(obj),
+            marked: false,
+        };
+        match self.free.pop() {
+            Some(ix) => {
+                self.slots[ix] = slot;
+                ObjRef(ix)
+            }
+            None => {
+                self.slots.push(slot);
+                ObjRef(self.slots.len() - 1)
+            }
+        }
+    }
+}
+
+/// Collector
+impl Heap {
+    /// If the next allocation should be preceded by a collection
+    pub fn should_collect(&self) -> bool {
+        self.config.stress || self.bytes > self.next_gc
+    }
+
+    /// Frees objects unreachable from the roots and returns the number of them
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = Value>) -> usize {
+        let before = self.bytes;
+
+        for value in roots {
+            self.mark_value(value);
+        }
+        self.trace();
+        let freed = self.sweep();
+
+        self.next_gc = (self.bytes * GC_GROW_FACTOR).max(GC_INITIAL_THRESHOLD);
+        self.stats.collections += 1;
+        self.stats.collected += freed;
+
+        if self.config.log {
+            eprintln!(
+                "-- gc: freed {} objects ({} bytes), {} objects ({} bytes) alive, next at {} bytes",
+                freed,
+                before - self.bytes,
+                self.len(),
+                self.bytes,
+                self.next_gc
+            );
+        }
+
+        freed
+    }
+
+    fn mark_value(&mut self, value: Value) {
+        if let Value::Obj(obj) = value {
+            self.mark_obj(obj);
+        }
+    }
+
+    fn mark_obj(&mut self, obj: ObjRef) {
+        let slot = &mut self.slots[obj.0];
+        if !slot.marked {
+            slot.marked = true;
+            self.gray.push(obj);
+        }
+    }
+
+    /// Marks objects referred to from the gray objects until there's no gray object
+    fn trace(&mut self) {
+        while let Some(obj) = self.gray.pop() {
+            match self.get(obj) {
+                // strings have no reference
+                Obj::Str(_) => {}
+            }
+        }
+    }
+
+    fn sweep(&mut self) -> usize {
+        let slots = &self.slots;
+        self.strings.retain(|_, obj| slots[obj.0].marked);
+
+        let mut freed = 0;
+        for (ix, slot) in self.slots.iter_mut().enumerate() {
+            if slot.marked {
+                slot.marked = false;
+            } else if let Some(obj) = slot.obj.take() {
+                self.bytes -= obj.size();
+                self.free.push(ix);
+                freed += 1;
+            }
+        }
+        freed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interning() {
+        let mut heap = Heap::new();
+        let a = heap.intern("lox");
+        let b = heap.intern_owned("lo".to_string() + "x");
+        let c = heap.intern("rs");
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(heap.len(), 2);
+        assert_eq!(heap.as_str(b), Some("lox"));
+    }
+
+    #[test]
+    fn collect() {
+        let mut heap = Heap::new();
+        let a = heap.intern("a");
+        heap.intern("b");
+        let roots = [Value::Obj(a), Value::Nil];
+
+        assert_eq!(heap.collect(roots), 1);
+        assert_eq!(heap.len(), 1);
+        assert_eq!(heap.as_str(a), Some("a"));
+
+        // the slot is reused and the string is interned again
+        let b = heap.intern("b");
+        assert_ne!(a, b);
+        assert_eq!(heap.intern("b"), b);
+
+        assert_eq!(heap.collect(None), 2);
+        let stats = heap.stats();
+        assert_eq!((stats.objects, stats.bytes), (0, 0));
+        assert_eq!((stats.collections, stats.collected), (2, 3));
+    }
+}
@@ -1,32 +1,136 @@
+pub mod budget;
 pub mod chunk;
+pub mod heap;
+pub mod value;
 
-use {anyhow::*, std::ops};
+use {
+    anyhow::{Context, Error, Result},
+    std::{
+        collections::HashMap,
+        fmt,
+        io::{self, Write},
+        sync::{atomic::AtomicBool, Arc},
+        time::Instant,
+    },
+    thiserror::Error,
+};
 
-use self::chunk::*;
+use crate::{compiler::CompileError, lex::span::ByteSpan};
 
-#[derive(Debug)]
+use self::{
+    budget::Budget,
+    chunk::*,
+    heap::{GcConfig, GcStats, Heap, ObjRef},
+    value::Value,
+};
+
+/// The maximum number of nested call frames (reserved for functions)
+pub const FRAMES_MAX: usize = 64;
+/// The maximum number of values on the stack
+pub const STACK_MAX: usize = FRAMES_MAX * 256;
+
+#[derive(Debug, Error)]
 pub enum VmError {
-    CompileError,
-    RuntimeError,
+    #[error("{0}")]
+    CompileError(#[from] CompileError),
+    /// Type error or such, located at the code the instruction is compiled from
+    #[error("{msg}")]
+    RuntimeError { msg: String, sp: ByteSpan },
+    /// Exceeded [`STACK_MAX`] (or [`FRAMES_MAX`])
+    #[error("stack overflow")]
+    StackOverflow,
+    /// Ran out of the instructions given with [`Vm::set_fuel`]
+    #[error("execution budget exhausted")]
+    OutOfFuel,
+    /// Passed the time given with [`Vm::set_deadline`]
+    #[error("deadline exceeded")]
+    DeadlineExceeded,
+    /// Stopped via [`Vm::interrupt_handle`]
+    #[error("interrupted")]
+    Interrupted,
 }
 
 /// Loxrs virtual machine
-#[derive(Debug)]
 pub struct Vm {
     chunk: ChunkData,
     ix: usize,
+    /// Index of the instruction being run (for error reports)
+    op_ix: usize,
     stack: Vec<Value>,
+    /// Objects referred to from the stack, the constants or the globals
+    heap: Heap,
+    /// Global variables, keyed by interned names. They live across chunks (e.g. REPL lines).
+    globals: HashMap<ObjRef, Value>,
+    /// Where `print` writes to
+    out: Box<dyn Write>,
+    /// Limits on instructions and time
+    budget: Budget,
+}
+
+impl fmt::Debug for Vm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Vm")
+            .field("chunk", &self.chunk)
+            .field("ix", &self.ix)
+            .field("stack", &self.stack)
+            .field("heap", &self.heap)
+            .field("globals", &self.globals)
+            .finish()
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Vm {
+    /// Creates a VM which prints to stdout
     pub fn new() -> Self {
+        Self::with_output(Box::new(io::stdout()))
+    }
+
+    /// Creates a VM which prints to the given writer
+    pub fn with_output(out: Box<dyn Write>) -> Self {
         Self {
             chunk: ChunkData::new(),
             ix: 0,
+            op_ix: 0,
             stack: Vec::with_capacity(256),
+            heap: Heap::new(),
+            globals: HashMap::new(),
+            out,
+            budget: Budget::new(),
         }
     }
 
+    pub fn out(&mut self) -> &mut dyn Write {
+        &mut *self.out
+    }
+
+    /// Limits the number of instructions to run. Running out of them fails with an error. `None`
+    /// means unlimited, which is the default.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.budget.set_fuel(fuel);
+    }
+
+    /// The number of instructions left (if limited)
+    pub fn fuel(&self) -> Option<u64> {
+        self.budget.fuel()
+    }
+
+    /// Makes the execution fail after the given time
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        self.budget.set_deadline(deadline);
+    }
+
+    /// A flag another thread can set to stop the execution with an error. It's cleared when the
+    /// error is reported.
+    pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+        self.budget.interrupt_handle()
+    }
+
     pub fn clear_stack(&mut self) {
         self.stack.clear();
     }
@@ -42,14 +146,62 @@ impl Vm {
     pub fn stack(&mut self) -> &Vec<Value> {
         &self.stack
     }
+
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    /// The compiler allocates string constants here
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    pub fn set_gc_config(&mut self, config: GcConfig) {
+        self.heap.set_config(config);
+    }
+
+    pub fn gc_stats(&self) -> GcStats {
+        self.heap.stats()
+    }
+
+    /// Frees objects unreachable from the stack, the constants of the chunk and the globals,
+    /// returning the number of them
+    pub fn collect_garbage(&mut self) -> usize {
+        self.collect_garbage_with(&[])
+    }
+
+    /// [`Vm::collect_garbage`] with extra roots, e.g. the constants of a chunk being compiled
+    fn collect_garbage_with(&mut self, extra_roots: &[Value]) -> usize {
+        let globals = self
+            .globals
+            .iter()
+            .flat_map(|(&name, &value)| [Value::Obj(name), value]);
+        let roots = self
+            .stack
+            .iter()
+            .chain(self.chunk.consts().iter())
+            .chain(extra_roots.iter())
+            .copied()
+            .chain(globals);
+        self.heap.collect(roots)
+    }
 }
 
 /// Run
 impl Vm {
+    /// Runs a chunk from the beginning
+    pub fn run_chunk(&mut self, chunk: ChunkData) -> Result<()> {
+        self.chunk = chunk;
+        self.ix = 0;
+        self.run()
+    }
+
     pub fn run(&mut self) -> Result<()> {
         let chunk_len = self.chunk.bytes().len();
         while self.ix < chunk_len {
+            self.budget.step()?;
             // consume the next instruction
+            self.op_ix = self.ix;
             let byte = self.chunk.read_u8(self.ix);
             self.ix += 1;
 
@@ -74,7 +226,7 @@ impl Vm {
                         .consts()
                         .get(ix as usize)
                         .ok_or(anyhow!("missing index after OpConst8"))?;
-                    self.stack.push(value);
+                    self.push(value)?;
                     // println!("{}, {} => {:?}", "byte1", ix, value);
                 }
 
@@ -87,26 +239,77 @@ impl Vm {
                         .consts()
                         .get(ix as usize)
                         .ok_or_else(|| anyhow!("missing index after OpConst16 at {}", ip))?;
-                    self.stack.push(value);
+                    self.push(value)?;
                     // println!("{}, {} => {:?}", "byte2", ix, value);
                 }
 
+                OpNil => self.push(Value::Nil)?,
+                OpTrue => self.push(Value::Bool(true))?,
+                OpFalse => self.push(Value::Bool(false))?,
+
                 OpNegate => {
-                    let v = -self.stack.pop().expect("error when interpretting OnNegate");
-                    self.stack.push(v);
+                    let n = match self.peek(0)? {
+                        Value::Number(n) => n,
+                        _ => return Err(self.error("operand must be a number")),
+                    };
+                    self.pop()?;
+                    self.push(Value::Number(-n))?;
                 }
 
-                OpAdd => {
-                    self.binary_op(ops::Add::add);
+                OpAdd => self.add()?,
+                OpSub => self.binary_op(|a, b| Value::Number(a - b))?,
+                OpMul => self.binary_op(|a, b| Value::Number(a * b))?,
+                OpDiv => self.binary_op(|a, b| Value::Number(a / b))?,
+
+                OpNot => {
+                    let v = self.pop()?;
+                    self.push(Value::Bool(v.is_falsey()))?;
                 }
-                OpSub => {
-                    self.binary_op(ops::Sub::sub);
+                OpEqual => {
+                    let b = self.pop()?;
+                    let a = self.pop()?;
+                    self.push(Value::Bool(a == b))?;
                 }
-                OpMul => {
-                    self.binary_op(ops::Mul::mul);
+                OpGreater => self.binary_op(|a, b| Value::Bool(a > b))?,
+                OpLess => self.binary_op(|a, b| Value::Bool(a < b))?,
+
+                OpPrint => {
+                    let v = self.pop()?;
+                    writeln!(self.out, "{}", v.display(&self.heap)).context("when printing")?;
                 }
-                OpDiv => {
-                    self.binary_op(ops::Div::div);
+                OpPop => {
+                    self.pop()?;
+                }
+
+                OpDefineGlobal => {
+                    let name = self.read_name()?;
+                    let value = self.peek(0)?;
+                    self.globals.insert(name, value);
+                    self.pop()?;
+                }
+                OpGetGlobal => {
+                    let name = self.read_name()?;
+                    let value = match self.globals.get(&name) {
+                        Some(&value) => value,
+                        None => return Err(self.undefined(name)),
+                    };
+                    self.push(value)?;
+                }
+                OpSetGlobal => {
+                    let name = self.read_name()?;
+                    let value = self.peek(0)?;
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(self.undefined(name)),
+                    }
+                }
+                OpGetLocal => {
+                    let slot = self.read_slot()?;
+                    self.push(self.stack[slot])?;
+                }
+                OpSetLocal => {
+                    let slot = self.read_slot()?;
+                    self.stack[slot] = self.peek(0)?;
                 }
             }
         }
@@ -114,12 +317,113 @@ impl Vm {
         Ok(())
     }
 
-    /// Pushes binary operator to the stack
+    /// Pushes a value to the stack, failing instead of growing it without bound
     #[inline]
-    fn binary_op(&mut self, oper: impl Fn(Value, Value) -> Value) {
-        let b = self.stack.pop().expect("binary_op b");
-        let a = self.stack.pop().expect("binary_op a");
-        self.stack.push(oper(a, b));
+    fn push(&mut self, value: Value) -> Result<()> {
+        if self.stack.len() >= STACK_MAX {
+            return Err(VmError::StackOverflow.into());
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Reads the name constant operand of a global variable instruction
+    fn read_name(&mut self) -> Result<ObjRef> {
+        let ix = self.chunk.read_u16(self.ix);
+        self.ix += 2;
+        match self.chunk.consts().get(ix as usize) {
+            Some(&Value::Obj(name)) => Ok(name),
+            _ => Err(anyhow!("missing name constant at {}", ix)),
+        }
+    }
+
+    /// Reads the stack slot operand of a local variable instruction
+    fn read_slot(&mut self) -> Result<usize> {
+        let slot = self.chunk.read_u8(self.ix) as usize;
+        self.ix += 1;
+        if slot < self.stack.len() {
+            Ok(slot)
+        } else {
+            Err(anyhow!("invalid stack slot {}", slot))
+        }
+    }
+
+    /// Pops a value, failing if the chunk is broken
+    #[inline]
+    fn pop(&mut self) -> Result<Value> {
+        self.stack.pop().ok_or_else(|| anyhow!("stack underflow"))
+    }
+
+    /// Looks at a value `distance` slots down from the top of the stack
+    #[inline]
+    fn peek(&self, distance: usize) -> Result<Value> {
+        self.stack
+            .len()
+            .checked_sub(distance + 1)
+            .map(|ix| self.stack[ix])
+            .ok_or_else(|| anyhow!("stack underflow"))
+    }
+
+    /// Applies a binary operator to two numbers on the stack
+    #[inline]
+    fn binary_op(&mut self, oper: impl Fn(f64, f64) -> Value) -> Result<()> {
+        let (a, b) = match (self.peek(1)?, self.peek(0)?) {
+            (Value::Number(a), Value::Number(b)) => (a, b),
+            _ => return Err(self.error("operands must be numbers")),
+        };
+        self.pop()?;
+        self.pop()?;
+        self.push(oper(a, b))
+    }
+
+    /// Adds two numbers or concatenates two strings
+    fn add(&mut self) -> Result<()> {
+        let (a, b) = (self.peek(1)?, self.peek(0)?);
+        let value = match (a, b) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            _ => match (a.as_str(&self.heap), b.as_str(&self.heap)) {
+                (Some(a), Some(b)) => {
+                    let s = format!("{}{}", a, b);
+                    Value::Obj(self.alloc_str(s))
+                }
+                _ => return Err(self.error("operands must be two numbers or two strings")),
+            },
+        };
+        self.pop()?;
+        self.pop()?;
+        self.push(value)
+    }
+
+    /// Allocates a string, collecting garbage beforehand if it's time to. Any object in use has to
+    /// be reachable from the roots at this point.
+    fn alloc_str(&mut self, s: String) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage();
+        }
+        self.heap.intern_owned(s)
+    }
+
+    /// Allocates a string constant for the compiler, collecting garbage beforehand if it's time
+    /// to. The constants of the chunk being compiled are kept alive along with the VM's roots.
+    pub fn alloc_const_str(&mut self, s: &str, consts: &[Value]) -> ObjRef {
+        if self.heap.should_collect() {
+            self.collect_garbage_with(consts);
+        }
+        self.heap.intern(s)
+    }
+
+    fn undefined(&self, name: ObjRef) -> Error {
+        let name = self.heap.as_str(name).unwrap_or_default();
+        self.error(format!("undefined variable `{}`", name))
+    }
+
+    /// Runtime error at the instruction being run
+    fn error(&self, msg: impl Into<String>) -> Error {
+        VmError::RuntimeError {
+            msg: msg.into(),
+            sp: self.chunk.span(self.op_ix),
+        }
+        .into()
     }
 }
 
@@ -135,27 +439,144 @@ mod tests {
         let mut vm = Vm::new();
         {
             let chunk = vm.chunk_mut();
+            let sp = ByteSpan::default();
 
             // use 2^x considering the accuracy of floating values
-            chunk.push_const(64.0);
-            chunk.push_const(32.0);
-            chunk.push_const(16.0);
+            chunk.push_const(64.0.into());
+            chunk.push_const(32.0.into());
+            chunk.push_const(16.0.into());
 
-            chunk.push_ix_u8(0); // 64.0
-            chunk.push_ix_u8(1); // 32.0
-            chunk.push_code(OpSub); // -
+            chunk.push_ix_u8(0, sp); // 64.0
+            chunk.push_ix_u8(1, sp); // 32.0
+            chunk.push_code(OpSub, sp); // -
 
-            chunk.push_ix_u16(2); // 16.0
-            chunk.push_code(OpDiv); // /
+            chunk.push_ix_u16(2, sp); // 16.0
+            chunk.push_code(OpDiv, sp); // /
 
-            chunk.push_code(OpNegate); // -
+            chunk.push_code(OpNegate, sp); // -
 
-            chunk.push_code(OpReturn);
+            chunk.push_code(OpReturn, sp);
         }
 
         match vm.run() {
             Err(why) => panic!("{:?}", why),
-            Ok(()) => assert_eq!(Some(&-2.0), vm.stack().last()),
+            Ok(()) => assert_eq!(Some(&Value::Number(-2.0)), vm.stack().last()),
+        }
+    }
+
+    #[test]
+    fn vm_stack_overflow() {
+        let mut vm = Vm::new();
+        {
+            let chunk = vm.chunk_mut();
+            let sp = ByteSpan::default();
+            chunk.push_const(1.0.into());
+            for _ in 0..=STACK_MAX {
+                chunk.push_ix_u8(0, sp);
+            }
+            chunk.push_code(OpReturn, sp);
+        }
+
+        let why = vm.run().unwrap_err();
+        assert!(matches!(
+            why.downcast_ref::<VmError>(),
+            Some(VmError::StackOverflow)
+        ));
+    }
+
+    #[test]
+    fn vm_out_of_fuel() {
+        let mut vm = Vm::new();
+        {
+            let chunk = vm.chunk_mut();
+            let sp = ByteSpan::default();
+            chunk.push_const(1.0.into());
+            chunk.push_ix_u8(0, sp);
+            chunk.push_code(OpNegate, sp);
+            chunk.push_code(OpReturn, sp);
+        }
+
+        vm.set_fuel(Some(2));
+        let why = vm.run().unwrap_err();
+        assert!(matches!(
+            why.downcast_ref::<VmError>(),
+            Some(VmError::OutOfFuel)
+        ));
+        assert_eq!(vm.fuel(), Some(0));
+    }
+
+    #[test]
+    fn vm_concat() {
+        let mut vm = Vm::new();
+        let sp = ByteSpan::default();
+        let a = vm.heap_mut().intern("lo");
+        let b = vm.heap_mut().intern("x");
+        {
+            let chunk = vm.chunk_mut();
+            chunk.push_const(a.into());
+            chunk.push_const(b.into());
+            chunk.push_ix_u8(0, sp);
+            chunk.push_ix_u8(1, sp);
+            chunk.push_code(OpAdd, sp);
+            chunk.push_code(OpReturn, sp);
+        }
+
+        vm.run().unwrap();
+        let lox = vm.heap_mut().intern("lox");
+        assert_eq!(vm.stack().last(), Some(&Value::Obj(lox)));
+    }
+
+    #[test]
+    fn vm_gc_stress() {
+        let mut vm = Vm::new();
+        vm.set_gc_config(GcConfig {
+            stress: true,
+            log: false,
+        });
+        let sp = ByteSpan::default();
+        let a = vm.heap_mut().intern("a");
+        vm.heap_mut().intern("garbage");
+        {
+            let chunk = vm.chunk_mut();
+            chunk.push_const(a.into());
+            // "a" + "a" + "a"
+            chunk.push_ix_u8(0, sp);
+            chunk.push_ix_u8(0, sp);
+            chunk.push_code(OpAdd, sp);
+            chunk.push_ix_u8(0, sp);
+            chunk.push_code(OpAdd, sp);
+            chunk.push_code(OpReturn, sp);
+        }
+
+        vm.run().unwrap();
+        let v = *vm.stack().last().unwrap();
+        assert_eq!(v.as_str(vm.heap()), Some("aaa"));
+        // "garbage" is freed, while the operands on the stack are kept alive
+        let stats = vm.gc_stats();
+        assert_eq!((stats.collections, stats.collected), (2, 1));
+        assert_eq!(stats.objects, 3);
+        vm.clear_stack();
+        vm.collect_garbage();
+        assert_eq!(vm.gc_stats().objects, 1);
+    }
+
+    #[test]
+    fn vm_type_error() {
+        let mut vm = Vm::new();
+        {
+            let chunk = vm.chunk_mut();
+            chunk.push_code(OpTrue, ByteSpan::from([1, 5]));
+            chunk.push_code(OpNegate, ByteSpan::from([0, 1]));
+            chunk.push_code(OpReturn, ByteSpan::default());
+        }
+
+        let why = vm.run().unwrap_err();
+        match why.downcast_ref::<VmError>() {
+            Some(VmError::RuntimeError { msg, sp }) => {
+                assert_eq!(msg, "operand must be a number");
+                assert_eq!(*sp, ByteSpan::from([0, 1]));
+            }
+            _ => panic!("expected a runtime error: {:?}", why),
         }
     }
 }
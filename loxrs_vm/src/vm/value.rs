@@ -0,0 +1,80 @@
+//! Dynamically typed values
+
+use std::fmt;
+
+use crate::vm::heap::{Heap, Obj, ObjRef};
+
+/// Value on the stack or in the constant table
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    /// Reference to a heap object. Since strings are interned, comparing the references compares
+    /// the strings.
+    Obj(ObjRef),
+}
+
+impl Value {
+    /// `nil` and `false` are falsey and everything else is truthy
+    pub fn is_falsey(&self) -> bool {
+        matches!(self, Value::Nil | Value::Bool(false))
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The string content if it's a string object
+    pub fn as_str<'h>(&self, heap: &'h Heap) -> Option<&'h str> {
+        match self {
+            Value::Obj(obj) => heap.as_str(*obj),
+            _ => None,
+        }
+    }
+
+    /// Makes the value printable, looking up objects in the heap
+    pub fn display(self, heap: &Heap) -> DisplayValue<'_> {
+        DisplayValue { value: self, heap }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<ObjRef> for Value {
+    fn from(obj: ObjRef) -> Self {
+        Value::Obj(obj)
+    }
+}
+
+/// [`Value`] with the [`Heap`] it refers to
+pub struct DisplayValue<'h> {
+    value: Value,
+    heap: &'h Heap,
+}
+
+impl fmt::Display for DisplayValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.value {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Obj(obj) => match self.heap.get(obj) {
+                Obj::Str(s) => write!(f, "{}", s),
+            },
+        }
+    }
+}
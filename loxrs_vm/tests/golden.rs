@@ -0,0 +1,33 @@
+//! Runs the scripts in `tests/lox` and checks their `// expect: ..` annotations
+
+use {
+    loxrs_vm::{golden, vm::heap::GcConfig},
+    std::path::Path,
+};
+
+fn check_scripts(gc: GcConfig) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox");
+    let report = golden::check_dir_with(&dir, gc).unwrap();
+    assert!(!report.passed.is_empty());
+
+    let failures = report
+        .failed
+        .iter()
+        .map(|(path, failures)| format!("{}:\n    {}", path.display(), failures.join("\n    ")))
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn scripts() {
+    self::check_scripts(GcConfig::default());
+}
+
+/// Objects that are not rooted would be freed while in use
+#[test]
+fn scripts_gc_stress() {
+    self::check_scripts(GcConfig {
+        stress: true,
+        log: false,
+    });
+}